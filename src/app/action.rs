@@ -0,0 +1,84 @@
+use winit::window::WindowId;
+
+/// Requests that change app-level state, queued by input handling and modules
+/// and applied by `App` once the current event has been dispatched
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+  Exit,
+  NewWindow,
+  CloseWindow,
+  TogglePause,
+  /// advances a paused simulation by one step
+  Step,
+  /// respawns every particle
+  Reset,
+  Screenshot,
+  CycleColorMode,
+  /// spawns `sim.particle_step` more particles
+  AddParticles,
+  /// removes the last `sim.particle_step` particles
+  RemoveParticles,
+  /// puts a circle obstacle at the cursor
+  PlaceObstacle,
+  /// removes the obstacle under the cursor
+  RemoveObstacle,
+  /// writes the simulation state to `sim.snapshot_path`
+  SaveSnapshot,
+  /// continues from the snapshot at `sim.snapshot_path`
+  LoadSnapshot,
+  /// shows or hides the HUD of every window
+  ToggleHud,
+  /// action registered by a module through `Module::actions`
+  Module(&'static str),
+}
+
+impl Action {
+  /// Built-in actions with their default key bindings
+  pub const BUILTIN: [(Action, &'static str); 15] = [
+    (Action::Exit, "Escape"),
+    (Action::NewWindow, "Space"),
+    (Action::CloseWindow, "Ctrl+W"),
+    (Action::TogglePause, "P"),
+    (Action::Step, "Period"),
+    (Action::Reset, "R"),
+    (Action::Screenshot, "F12"),
+    (Action::CycleColorMode, "C"),
+    (Action::AddParticles, "Equal"),
+    (Action::RemoveParticles, "Minus"),
+    (Action::PlaceObstacle, "O"),
+    (Action::RemoveObstacle, "Shift+O"),
+    (Action::SaveSnapshot, "F5"),
+    (Action::LoadSnapshot, "F9"),
+    (Action::ToggleHud, "H"),
+  ];
+
+  /// Name used for the action in the `[keys]` config section
+  pub fn name(&self) -> &'static str {
+    match self {
+      Action::Exit => "exit",
+      Action::NewWindow => "new_window",
+      Action::CloseWindow => "close_window",
+      Action::TogglePause => "pause",
+      Action::Step => "step",
+      Action::Reset => "reset",
+      Action::Screenshot => "screenshot",
+      Action::CycleColorMode => "cycle_color_mode",
+      Action::AddParticles => "add_particles",
+      Action::RemoveParticles => "remove_particles",
+      Action::PlaceObstacle => "place_obstacle",
+      Action::RemoveObstacle => "remove_obstacle",
+      Action::SaveSnapshot => "save_snapshot",
+      Action::LoadSnapshot => "load_snapshot",
+      Action::ToggleHud => "toggle_hud",
+      Action::Module(name) => name,
+    }
+  }
+}
+
+/// An action together with the window it was requested from, window specific actions
+/// like `CloseWindow` and `Screenshot` apply to that window
+#[derive(Clone, Copy, Debug)]
+pub struct ActionRequest {
+  pub action: Action,
+  pub window_id: Option<WindowId>,
+}
@@ -1,85 +1,349 @@
 use super::state::State;
-use crate::app::module::Module;
-use tracing::error;
+use crate::{
+  app::{
+    action::{Action, ActionRequest},
+    input::KeyBindings,
+    module::{Module, ModuleContext},
+    scheduler::Scheduler,
+  },
+  config::Config,
+  manifest::RunRecorder,
+};
+use std::{
+  collections::{HashMap, HashSet},
+  path::{Path, PathBuf},
+  time::Instant,
+};
+use tracing::{error, info};
 use winit::{
   application::ApplicationHandler,
   event::{ElementState, KeyEvent, WindowEvent},
   event_loop::ActiveEventLoop,
-  keyboard::{KeyCode, PhysicalKey},
+  keyboard::{ModifiersState, PhysicalKey},
   window::WindowId,
 };
 
-#[derive(Default)]
 pub struct App {
+  config: Config,
+  recorder: RunRecorder,
+  /// snapshot loaded once the app state exists
+  initial_snapshot: Option<PathBuf>,
   state: Option<State>,
   modules: Vec<Box<dyn Module>>,
+  actions: Vec<ActionRequest>,
+  key_bindings: Option<KeyBindings>,
+  modifiers: ModifiersState,
+
+  /// windows whose redraw arrived since the last frame, drawn together in `about_to_wait`
+  redraws: HashSet<WindowId>,
+
+  scheduler: Scheduler,
+  started: Instant,
+  last_update: Option<Instant>,
+  hud_visible: bool,
 }
 
 impl ApplicationHandler for App {
   fn resumed(&mut self, event_loop: &ActiveEventLoop) {
-    let state_future = State::new(event_loop);
+    if self.state.is_some() {
+      return;
+    }
+
+    let state_future = State::new(event_loop, &self.config);
 
     // cannot await & return result because of function signature
     match futures::executor::block_on(state_future) {
       Ok(state) => self.state = Some(state),
-      Err(e) => panic!("Failed to create app state: {e}"),
+      Err(e) => {
+        error!("Failed to create app state: {e}");
+        event_loop.exit();
+        return;
+      }
+    }
+
+    let state = self.state.as_ref().unwrap();
+    self.recorder.start(&self.config, state.adapter_info());
+
+    if let Some(path) = self.initial_snapshot.take() {
+      self.load_snapshot(&path);
     }
+
+    let module_actions: Vec<_> = self.modules.iter().flat_map(|module| module.actions()).collect();
+    let key_bindings = KeyBindings::new(&self.config.keys, &module_actions);
+    key_bindings.log();
+    self.key_bindings = Some(key_bindings);
+
+    self.dispatch(None, |module, ctx| module.on_init(ctx));
+    self.apply_actions(event_loop);
   }
 
   fn window_event(&mut self, event_loop: &ActiveEventLoop, window_id: WindowId, event: WindowEvent) {
-    let Some(state) = &mut self.state else {
+    if self.state.is_none() {
       error!("App state does not exit");
       return;
-    };
+    }
 
-    match event {
-      WindowEvent::CloseRequested => {
-        state.request_close(window_id);
+    self.dispatch(Some(window_id), |module, ctx| module.on_event(ctx, &event));
 
-        if !state.has_windows() {
-          event_loop.exit();
-        }
-      }
+    match event {
+      WindowEvent::CloseRequested => self.request(Action::CloseWindow, Some(window_id)),
       WindowEvent::RedrawRequested => {
-        self.modules.iter_mut().for_each(|module| module.on_render(window_id));
-        state.render(window_id);
-        state.request_redraw(window_id);
+        self.redraws.insert(window_id);
       }
       WindowEvent::Resized(new_size) => {
-        state.resize(window_id, new_size);
+        self.state.as_mut().unwrap().resize(window_id, new_size);
+        self.dispatch(Some(window_id), |module, ctx| module.on_resize(ctx, new_size));
       }
+      WindowEvent::ModifiersChanged(modifiers) => self.modifiers = modifiers.state(),
+      WindowEvent::CursorMoved { position, .. } => self.state.as_mut().unwrap().cursor_moved(window_id, Some(position)),
+      WindowEvent::CursorLeft { .. } => self.state.as_mut().unwrap().cursor_moved(window_id, None),
+      WindowEvent::MouseInput { state, button, .. } => self.state.as_mut().unwrap().mouse_input(window_id, state, button),
+      WindowEvent::Focused(false) => self.state.as_mut().unwrap().release_buttons(window_id),
       WindowEvent::KeyboardInput {
         event:
           KeyEvent {
-            physical_key: PhysicalKey::Code(KeyCode::Space),
+            physical_key: PhysicalKey::Code(key),
             state: ElementState::Released,
             repeat: false,
             ..
           },
         ..
       } => {
-        let task = state.add_window(event_loop);
-
-        let window_id = match futures::executor::block_on(task) {
-          Ok(id) => id,
-          Err(e) => panic!("Failed to create new window: {e}"),
-        };
-
-        state.request_redraw(window_id);
+        if let Some(action) = self.key_bindings.as_ref().and_then(|bindings| bindings.action(key, self.modifiers)) {
+          self.request(action, Some(window_id));
+        }
       }
       _ => (),
     }
+
+    self.apply_actions(event_loop);
   }
 
-  fn about_to_wait(&mut self, _: &ActiveEventLoop) {
+  fn about_to_wait(&mut self, event_loop: &ActiveEventLoop) {
+    if let Some(state) = self.state.as_mut()
+      && state.is_device_lost()
+      && let Err(e) = futures::executor::block_on(state.recover_device())
+    {
+      error!("Failed to recover from device loss, shutting down: {e}");
+      event_loop.exit();
+      return;
+    }
+
+    let now = Instant::now();
+    let dt = self.last_update.map(|last| now - last).unwrap_or_default();
+    self.last_update = Some(now);
+
+    self.dispatch(None, |module, ctx| module.on_update(ctx, dt));
+
+    let steps = self.scheduler.advance(dt);
     if let Some(state) = self.state.as_mut() {
-      state.compute();
+      state.compute(steps, self.scheduler.step_dt());
+      if steps > 0 {
+        self.dispatch(None, |module, ctx| module.on_compute(ctx, steps));
+      }
+    }
+    self.render();
+
+    // the next frame is drawn once the windows' redraws arrive
+    if let Some(state) = &self.state {
+      for window_id in state.window_ids() {
+        state.request_redraw(window_id);
+      }
     }
+
+    self.apply_actions(event_loop);
+  }
+
+  fn exiting(&mut self, _: &ActiveEventLoop) {
+    self.dispatch(None, |module, ctx| module.on_shutdown(ctx));
+    self.recorder.finish(self.scheduler.steps());
   }
 }
 
 impl App {
+  pub fn new(config: Config, recorder: RunRecorder, initial_snapshot: Option<PathBuf>) -> App {
+    let mut scheduler = Scheduler::new(&config.sim.timestep);
+    // a replay stops where the recorded run ended
+    scheduler.set_step_limit(recorder.replay.as_ref().map(|replay| replay.steps));
+    let hud_visible = config.hud.enabled;
+
+    App {
+      config,
+      recorder,
+      initial_snapshot,
+      state: None,
+      modules: Vec::new(),
+      actions: Vec::new(),
+      key_bindings: None,
+      modifiers: ModifiersState::empty(),
+      redraws: HashSet::new(),
+      scheduler,
+      started: Instant::now(),
+      last_update: None,
+      hud_visible,
+    }
+  }
+
   pub fn add_module(&mut self, module: Box<dyn Module>) {
     self.modules.push(module);
   }
+
+  /// Draws every window with a pending redraw into a single submit, modules get to draw on top of each window
+  fn render(&mut self) {
+    if self.state.is_none() || self.redraws.is_empty() {
+      return;
+    }
+
+    let window_ids: Vec<WindowId> = self.redraws.drain().collect();
+    for &window_id in &window_ids {
+      self.dispatch(Some(window_id), |module, ctx| module.on_render(ctx));
+    }
+
+    let mut hud_lines = HashMap::new();
+    if self.hud_visible {
+      for &window_id in &window_ids {
+        let mut lines = self.hud_lines();
+        self.dispatch(Some(window_id), |module, ctx| module.on_hud(ctx, &mut lines));
+        hud_lines.insert(window_id, lines);
+      }
+    }
+
+    let elapsed = self.started.elapsed();
+    let (modules, actions) = (&mut self.modules, &mut self.actions);
+    let state = self.state.as_mut().unwrap();
+    let rendered = state.render(&window_ids, &hud_lines, |gpu, window, gpu_timings, encoder, view| {
+      let mut ctx = ModuleContext::new(gpu, Some(window), gpu_timings, elapsed, actions);
+      for module in modules.iter_mut() {
+        module.on_draw(&mut ctx, encoder, view, window.surface_config.format);
+      }
+    });
+
+    if let Err(e) = rendered {
+      error!("Failed to render, shutting down: {e}");
+      self.request(Action::Exit, None);
+    }
+  }
+
+  /// Sim time and pause state followed by the simulation lines of `State::hud_lines`
+  fn hud_lines(&self) -> Vec<String> {
+    let Some(state) = &self.state else {
+      return Vec::new();
+    };
+
+    let paused = if self.scheduler.is_paused() { " (paused)" } else { "" };
+    let mut lines = vec![format!(
+      "Sim time: {:.1}s, step {}{paused}",
+      self.scheduler.sim_time(),
+      self.scheduler.steps()
+    )];
+    lines.extend(state.hud_lines());
+    lines
+  }
+
+  /// Calls `hook` on every module with a context for `window_id`
+  fn dispatch(&mut self, window_id: Option<WindowId>, mut hook: impl FnMut(&mut dyn Module, &mut ModuleContext)) {
+    let Some(state) = &self.state else {
+      return;
+    };
+
+    let mut ctx = state.module_context(window_id, self.started.elapsed(), &mut self.actions);
+    for module in self.modules.iter_mut() {
+      hook(module.as_mut(), &mut ctx);
+    }
+  }
+
+  /// Continues from the snapshot at `path`, sim time restarts at 0
+  fn load_snapshot(&mut self, path: &Path) {
+    let Some(state) = self.state.as_mut() else {
+      return;
+    };
+
+    match state.load_snapshot(path, self.scheduler.step_dt()) {
+      Ok(()) => self.scheduler.reset(),
+      Err(e) => error!("Failed to load snapshot: {e}"),
+    }
+  }
+
+  fn request(&mut self, action: Action, window_id: Option<WindowId>) {
+    self.actions.push(ActionRequest { action, window_id });
+  }
+
+  fn apply_actions(&mut self, event_loop: &ActiveEventLoop) {
+    if self.state.is_none() {
+      return;
+    }
+
+    for ActionRequest { action, window_id } in std::mem::take(&mut self.actions) {
+      let state = self.state.as_mut().unwrap();
+
+      match action {
+        Action::Exit => event_loop.exit(),
+        Action::NewWindow => {
+          let task = state.add_window(event_loop);
+
+          if let Err(e) = futures::executor::block_on(task) {
+            error!("Failed to create new window: {e}");
+          }
+        }
+        Action::CloseWindow => {
+          let Some(window_id) = window_id else {
+            continue;
+          };
+
+          self.dispatch(Some(window_id), |module, ctx| module.on_window_close(ctx));
+
+          let state = self.state.as_mut().unwrap();
+          state.request_close(window_id);
+          if !state.has_windows() {
+            event_loop.exit();
+          }
+        }
+        Action::TogglePause => {
+          self.scheduler.toggle_pause();
+          let verb = if self.scheduler.is_paused() { "paused" } else { "resumed" };
+          info!("Simulation {verb} at {:.2}s", self.scheduler.sim_time());
+        }
+        Action::Step => self.scheduler.request_step(),
+        Action::Reset => {
+          state.reset();
+          self.scheduler.reset();
+        }
+        Action::Screenshot => {
+          let Some(window_id) = window_id else {
+            continue;
+          };
+
+          match state.screenshot(window_id, &self.config.window.screenshot_dir) {
+            Ok(path) => info!("Screenshot saved to {}", path.display()),
+            Err(e) => error!("Failed to take screenshot: {e}"),
+          }
+        }
+        Action::CycleColorMode => state.cycle_color_mode(),
+        Action::AddParticles => state.change_particle_count(self.config.sim.particle_step as i64),
+        Action::RemoveParticles => state.change_particle_count(-(self.config.sim.particle_step as i64)),
+        Action::PlaceObstacle | Action::RemoveObstacle => {
+          if let Some(window_id) = window_id {
+            state.edit_obstacles(window_id, action == Action::PlaceObstacle);
+          }
+        }
+        Action::SaveSnapshot => {
+          if let Err(e) = state.save_snapshot(&self.config.sim.snapshot_path, self.scheduler.step_dt()) {
+            error!("Failed to save snapshot: {e}");
+          }
+        }
+        Action::LoadSnapshot => self.load_snapshot(&self.config.sim.snapshot_path.clone()),
+        Action::ToggleHud => self.hud_visible = !self.hud_visible,
+        Action::Module(name) => self.dispatch(window_id, |module, ctx| {
+          if module.actions().iter().any(|&(action, _)| action == name) {
+            module.on_action(ctx, name);
+          }
+        }),
+      }
+    }
+
+    // actions requested by hooks dispatched above
+    if !self.actions.is_empty() {
+      self.apply_actions(event_loop);
+    }
+  }
 }
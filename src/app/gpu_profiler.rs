@@ -0,0 +1,165 @@
+use crate::{app::gpu_wrapper::GpuWrapper, particle_sim::readback::Readback};
+use std::collections::{BTreeMap, VecDeque};
+use tracing::warn;
+use wgpu::{
+  Buffer, BufferDescriptor, BufferUsages, CommandEncoder, ComputePassTimestampWrites, Features, QUERY_SIZE, QuerySet, QuerySetDescriptor, QueryType,
+  RenderPassTimestampWrites,
+};
+use winit::window::WindowId;
+
+/// passes a single frame can measure, each takes a begin and an end timestamp
+const MAX_SCOPES: u32 = 64;
+/// frames whose timestamps may be on their way back at once, frames beyond that go unmeasured
+const MAX_PENDING_FRAMES: usize = 3;
+/// frames the rolling averages are taken over
+const ROLLING_FRAMES: usize = 60;
+
+/// Rolling average GPU time per pass label, in milliseconds
+pub type GpuTimings = BTreeMap<String, f64>;
+
+/// Measures how long compute and render passes take on the GPU with timestamp queries.
+///
+/// Passes ask for timestamp writes while a frame is recorded, `resolve` and `end_frame` send the
+/// timestamps back to the CPU, where they are collected without blocking a few frames later
+pub struct GpuProfiler {
+  query_set: QuerySet,
+  resolve_buffer: Buffer,
+  /// nanoseconds per timestamp tick
+  period_ns: f64,
+
+  /// label of every scope of the frame being recorded, scope `i` owns queries `2i` and `2i + 1`
+  labels: Vec<String>,
+  pending: VecDeque<(Vec<String>, Readback<u64>)>,
+
+  samples: BTreeMap<String, VecDeque<f64>>,
+  timings: GpuTimings,
+}
+
+impl GpuProfiler {
+  /// `None` if the device was created without `Features::TIMESTAMP_QUERY`
+  pub fn new(gpu: &GpuWrapper) -> Option<GpuProfiler> {
+    if !gpu.device.features().contains(Features::TIMESTAMP_QUERY) {
+      return None;
+    }
+
+    let query_count = MAX_SCOPES * 2;
+    let query_set = gpu.device.create_query_set(&QuerySetDescriptor {
+      label: Some("Profiler query set"),
+      ty: QueryType::Timestamp,
+      count: query_count,
+    });
+    let resolve_buffer = gpu.device.create_buffer(&BufferDescriptor {
+      label: Some("Profiler resolve buffer"),
+      size: (query_count * QUERY_SIZE) as u64,
+      usage: BufferUsages::QUERY_RESOLVE | BufferUsages::COPY_SRC,
+      mapped_at_creation: false,
+    });
+
+    Some(GpuProfiler {
+      query_set,
+      resolve_buffer,
+      period_ns: gpu.queue.get_timestamp_period() as f64,
+      labels: Vec::new(),
+      pending: VecDeque::new(),
+      samples: BTreeMap::new(),
+      timings: GpuTimings::new(),
+    })
+  }
+
+  /// Label the render pass of `window_id` is measured under
+  pub fn render_label(window_id: WindowId) -> String {
+    format!("render {window_id:?}")
+  }
+
+  /// Timestamp writes measuring a compute pass as `label`, `None` once the frame ran out of queries
+  pub fn compute_scope(&mut self, label: &str) -> Option<ComputePassTimestampWrites<'_>> {
+    let (begin, end) = self.scope(label)?;
+    Some(ComputePassTimestampWrites {
+      query_set: &self.query_set,
+      beginning_of_pass_write_index: Some(begin),
+      end_of_pass_write_index: Some(end),
+    })
+  }
+
+  /// Timestamp writes measuring a render pass as `label`, `None` once the frame ran out of queries
+  pub fn render_scope(&mut self, label: &str) -> Option<RenderPassTimestampWrites<'_>> {
+    let (begin, end) = self.scope(label)?;
+    Some(RenderPassTimestampWrites {
+      query_set: &self.query_set,
+      beginning_of_pass_write_index: Some(begin),
+      end_of_pass_write_index: Some(end),
+    })
+  }
+
+  /// Records resolving every scope of the frame, `encoder` has to be submitted after all measured passes
+  pub fn resolve(&self, encoder: &mut CommandEncoder) {
+    if self.labels.is_empty() || self.pending.len() >= MAX_PENDING_FRAMES {
+      return;
+    }
+
+    let query_count = self.labels.len() as u32 * 2;
+    encoder.resolve_query_set(&self.query_set, 0..query_count, &self.resolve_buffer, 0);
+  }
+
+  /// Starts copying the resolved timestamps back once the encoder from `resolve` was submitted,
+  /// and folds every frame that has arrived since the last call into the averages
+  pub fn end_frame(&mut self, gpu: &GpuWrapper) {
+    let labels = std::mem::take(&mut self.labels);
+    if !labels.is_empty() && self.pending.len() < MAX_PENDING_FRAMES {
+      let readback = Readback::new(gpu, &self.resolve_buffer, labels.len() * 2);
+      self.pending.push_back((labels, readback));
+    }
+
+    while let Some((_, readback)) = self.pending.front_mut() {
+      let Some(result) = readback.try_take() else {
+        break;
+      };
+
+      let (labels, _) = self.pending.pop_front().unwrap();
+      match result {
+        Ok(timestamps) => self.add_frame(&labels, &timestamps),
+        Err(e) => warn!("Failed to read GPU timestamps back: {e}"),
+      }
+    }
+  }
+
+  pub fn timings(&self) -> &GpuTimings {
+    &self.timings
+  }
+
+  /// Drops the averages of `label`, e.g. once its window was closed
+  pub fn forget(&mut self, label: &str) {
+    self.samples.remove(label);
+    self.timings.remove(label);
+  }
+
+  fn scope(&mut self, label: &str) -> Option<(u32, u32)> {
+    let index = self.labels.len() as u32;
+    if index >= MAX_SCOPES {
+      return None;
+    }
+
+    self.labels.push(label.to_string());
+    Some((index * 2, index * 2 + 1))
+  }
+
+  /// Scopes sharing a label within a frame, e.g. several compute steps, add up
+  fn add_frame(&mut self, labels: &[String], timestamps: &[u64]) {
+    let mut frame: BTreeMap<&str, f64> = BTreeMap::new();
+    for (label, pair) in labels.iter().zip(timestamps.chunks_exact(2)) {
+      let ticks = pair[1].saturating_sub(pair[0]);
+      *frame.entry(label).or_default() += ticks as f64 * self.period_ns / 1_000_000.0;
+    }
+
+    for (label, ms) in frame {
+      let samples = self.samples.entry(label.to_string()).or_default();
+      samples.push_back(ms);
+      if samples.len() > ROLLING_FRAMES {
+        samples.pop_front();
+      }
+
+      let average = samples.iter().sum::<f64>() / samples.len() as f64;
+      self.timings.insert(label.to_string(), average);
+    }
+  }
+}
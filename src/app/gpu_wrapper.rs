@@ -1,10 +1,79 @@
-use wgpu::{Adapter, Device, Instance, Queue};
+use crate::config::GpuConfig;
+use std::sync::{
+  Arc,
+  atomic::{AtomicBool, Ordering},
+};
+use tracing::{error, info};
+use wgpu::{
+  Adapter, Backends, Device, DeviceDescriptor, DeviceLostReason, Features, Instance, InstanceDescriptor, Queue, RequestAdapterError,
+  RequestAdapterOptions, RequestDeviceError,
+};
 
 pub struct GpuWrapper {
   pub instance: Instance,
   pub adapter: Adapter,
   pub device: Device,
   pub queue: Queue,
+  /// set from the device lost callback, the whole wrapper has to be recreated once this is true
+  device_lost: Arc<AtomicBool>,
+}
+
+impl GpuWrapper {
+  pub async fn new(config: &GpuConfig) -> Result<GpuWrapper, GpuWrapperError> {
+    let mut instance_desc = InstanceDescriptor::default();
+    if let Some(backends) = &config.backends {
+      instance_desc.backends = Backends::from_comma_list(backends);
+    }
+    let instance = Instance::new(&instance_desc);
+
+    let adapter_opts = RequestAdapterOptions {
+      power_preference: config.power_preference,
+      force_fallback_adapter: config.force_fallback_adapter,
+      compatible_surface: None,
+    };
+    let adapter = instance.request_adapter(&adapter_opts).await?;
+
+    // timestamp queries are optional, the profiler stays off without them
+    let mut required_features = Features::empty();
+    if config.profile {
+      if adapter.features().contains(Features::TIMESTAMP_QUERY) {
+        required_features |= Features::TIMESTAMP_QUERY;
+      } else {
+        info!("Adapter doesn't support timestamp queries, GPU profiling is off");
+      }
+    }
+
+    let device_desc = DeviceDescriptor {
+      required_features,
+      ..Default::default()
+    };
+    let (device, queue) = adapter.request_device(&device_desc).await?;
+
+    let device_lost = Arc::new(AtomicBool::new(false));
+    let flag = device_lost.clone();
+    device.set_device_lost_callback(move |reason, message| {
+      // `Destroyed` is reported when the device is dropped on purpose
+      if reason != DeviceLostReason::Destroyed {
+        error!("GPU device lost ({reason:?}): {message}");
+        flag.store(true, Ordering::Release);
+      }
+    });
+
+    // errors are expected while the device is lost, log them instead of panicking
+    device.on_uncaptured_error(Arc::new(|e| error!("Uncaptured GPU error: {e}")));
+
+    Ok(GpuWrapper {
+      instance,
+      adapter,
+      device,
+      queue,
+      device_lost,
+    })
+  }
+
+  pub fn is_device_lost(&self) -> bool {
+    self.device_lost.load(Ordering::Acquire)
+  }
 }
 
 impl<'a> From<&'a GpuWrapper> for (&'a Instance, &'a Adapter, &'a Device, &'a Queue) {
@@ -12,3 +81,13 @@ impl<'a> From<&'a GpuWrapper> for (&'a Instance, &'a Adapter, &'a Device, &'a Qu
     (&gpu.instance, &gpu.adapter, &gpu.device, &gpu.queue)
   }
 }
+
+#[allow(clippy::enum_variant_names)]
+#[derive(thiserror::Error, Debug)]
+pub enum GpuWrapperError {
+  #[error("Request adapter error: {0}")]
+  RequestAdapterError(#[from] RequestAdapterError),
+
+  #[error("Request device error: {0}")]
+  RequestDeviceError(#[from] RequestDeviceError),
+}
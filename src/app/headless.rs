@@ -0,0 +1,186 @@
+use crate::{
+  app::{
+    gpu_wrapper::{GpuWrapper, GpuWrapperError},
+    offscreen::{OffscreenError, OffscreenTarget},
+    scheduler,
+  },
+  config::Config,
+  manifest::RunRecorder,
+  particle_sim::{
+    compute_pass::ComputePass,
+    obstacle::ObstacleShape,
+    particle_sim::ParticleSim,
+    readback::ReadbackError,
+    render_pass::RenderTarget,
+    snapshot::{Snapshot, SnapshotError},
+    view::WindowView,
+    window::Window,
+  },
+};
+use std::path::{Path, PathBuf};
+use tracing::info;
+use wgpu::{CommandEncoderDescriptor, TextureFormat};
+
+const OFFSCREEN_FORMAT: TextureFormat = TextureFormat::Rgba8UnormSrgb;
+
+pub struct HeadlessState {
+  gpu: GpuWrapper,
+  /// shows the viewport rectangle, which never moves
+  view: WindowView,
+  /// windows the simulation keeps particles in, just the viewport unless a snapshot brought its own
+  windows: Vec<Window>,
+  target: OffscreenTarget,
+  sim: ParticleSim,
+  seed: u64,
+  /// simulated seconds per step, every headless frame runs exactly one step
+  step_dt: f32,
+}
+
+impl HeadlessState {
+  pub async fn new(viewport: Window, config: &Config) -> Result<HeadlessState, HeadlessError> {
+    let gpu = GpuWrapper::new(&config.gpu).await?;
+    let target = OffscreenTarget::new(&gpu, viewport.size(), OFFSCREEN_FORMAT);
+    let sim = ParticleSim::init(&gpu, OFFSCREEN_FORMAT, &config.sim);
+    let view = WindowView::new(&gpu.device);
+    view.update(&gpu.queue, &viewport);
+
+    Ok(HeadlessState {
+      gpu,
+      view,
+      windows: vec![viewport],
+      target,
+      sim,
+      seed: config.sim.seed.unwrap_or_default(),
+      step_dt: scheduler::step_dt(&config.sim.timestep) as f32,
+    })
+  }
+
+  /// Continues from `snapshot`, including the window rectangles it was saved with
+  pub fn load_snapshot(&mut self, snapshot: Snapshot, config: &Config) -> Result<(), HeadlessError> {
+    // the particle buffers would clamp the count and no longer fit the snapshot
+    snapshot.check_particle_count(ComputePass::max_particles(&self.gpu.device))?;
+
+    let mut sim_config = config.sim.clone();
+    sim_config.seed = Some(snapshot.seed);
+    sim_config.rules = snapshot.params.rules;
+    sim_config.obstacles = snapshot.obstacles.iter().map(ObstacleShape::from).collect();
+
+    if !snapshot.windows.is_empty() {
+      self.windows = snapshot.windows;
+    }
+    self.seed = snapshot.seed;
+    self.sim = ParticleSim::init_with_particles(&self.gpu, OFFSCREEN_FORMAT, &sim_config, snapshot.particles, snapshot.spawned);
+    self.sim.set_step_index(snapshot.params.step_index);
+    Ok(())
+  }
+
+  /// Blocks until the particles are copied back
+  pub fn snapshot(&self) -> Result<Snapshot, HeadlessError> {
+    let particles = self.sim.read_particles(&self.gpu)?;
+
+    Ok(Snapshot {
+      seed: self.seed,
+      spawned: self.sim.spawned(),
+      params: self.sim.params(&self.windows, self.step_dt),
+      windows: self.windows.clone(),
+      obstacles: self.sim.obstacles().to_vec(),
+      particles,
+    })
+  }
+
+  /// Runs one fixed-size compute step and renders the result into the offscreen target
+  pub fn step(&mut self) {
+    let mut command_encoder = self.gpu.device.create_command_encoder(&CommandEncoderDescriptor::default());
+    self
+      .sim
+      .compute(&mut command_encoder, &self.gpu, self.windows.clone(), self.step_dt, None);
+    let target = RenderTarget::new(self.target.format());
+    self
+      .sim
+      .render(&mut command_encoder, &self.gpu, &self.view, &self.target.view, target, None);
+
+    self.gpu.queue.submit(Some(command_encoder.finish()));
+  }
+
+  pub fn save_png(&self, path: &Path) -> Result<(), HeadlessError> {
+    Ok(self.target.save_png(&self.gpu, path)?)
+  }
+}
+
+/// Steps the simulation for a fixed number of frames without creating any windows
+pub struct HeadlessApp {
+  pub config: Config,
+  pub frames: u32,
+  pub output_dir: PathBuf,
+  pub save_every_frame: bool,
+  pub recorder: RunRecorder,
+  /// snapshot to start from instead of freshly spawned particles
+  pub load_snapshot: Option<PathBuf>,
+  /// where to save a snapshot after the last frame
+  pub save_snapshot: Option<PathBuf>,
+}
+
+impl HeadlessApp {
+  pub fn new(config: Config) -> HeadlessApp {
+    HeadlessApp {
+      config,
+      frames: 1,
+      output_dir: PathBuf::from("headless_output"),
+      save_every_frame: false,
+      recorder: RunRecorder::default(),
+      load_snapshot: None,
+      save_snapshot: None,
+    }
+  }
+
+  pub async fn run(&mut self) -> Result<(), HeadlessError> {
+    let [width, height] = self.config.window.size.unwrap_or([1024, 1024]);
+    let viewport = Window::new([0.0, 0.0], [width, height]);
+
+    let mut state = HeadlessState::new(viewport, &self.config).await?;
+    self.recorder.start(&self.config, state.gpu.adapter.get_info());
+
+    if let Some(path) = &self.load_snapshot {
+      state.load_snapshot(Snapshot::load(path)?, &self.config)?;
+      info!("Continuing from snapshot {}", path.display());
+    }
+    std::fs::create_dir_all(&self.output_dir)?;
+
+    for frame in 0..self.frames {
+      state.step();
+
+      if self.save_every_frame || frame + 1 == self.frames {
+        let path = self.output_dir.join(format!("frame_{frame:05}.png"));
+        state.save_png(&path)?;
+        info!("Frame {frame} written to {}", path.display());
+      }
+    }
+
+    if let Some(path) = &self.save_snapshot {
+      state.snapshot()?.save(path)?;
+      info!("Snapshot saved to {}", path.display());
+    }
+
+    self.recorder.finish(self.frames as u64);
+    Ok(())
+  }
+}
+
+#[allow(clippy::enum_variant_names)]
+#[derive(thiserror::Error, Debug)]
+pub enum HeadlessError {
+  #[error("GPU error: {0}")]
+  GpuError(#[from] GpuWrapperError),
+
+  #[error("Offscreen target error: {0}")]
+  OffscreenError(#[from] OffscreenError),
+
+  #[error("IO error: {0}")]
+  IoError(#[from] std::io::Error),
+
+  #[error("Readback error: {0}")]
+  ReadbackError(#[from] ReadbackError),
+
+  #[error("Snapshot error: {0}")]
+  SnapshotError(#[from] SnapshotError),
+}
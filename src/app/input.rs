@@ -0,0 +1,178 @@
+use crate::app::action::Action;
+use serde::{Deserialize, de::value::StrDeserializer};
+use std::{collections::BTreeMap, fmt, str::FromStr};
+use tracing::{info, warn};
+use winit::keyboard::{KeyCode, ModifiersState};
+
+/// Key with the exact set of modifiers that has to be held, written as e.g. "Ctrl+Shift+KeyS".
+/// Key names follow winit's `KeyCode`, single letters and digits are accepted as shorthand
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct KeyBinding {
+  pub key: KeyCode,
+  pub modifiers: ModifiersState,
+}
+
+impl FromStr for KeyBinding {
+  type Err = KeyBindingError;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let mut parts: Vec<&str> = s.split('+').map(str::trim).collect();
+    let key = parts.pop().filter(|key| !key.is_empty()).ok_or(KeyBindingError::MissingKey)?;
+
+    let mut modifiers = ModifiersState::empty();
+    for part in parts {
+      modifiers |= match part.to_ascii_lowercase().as_str() {
+        "ctrl" | "control" => ModifiersState::CONTROL,
+        "shift" => ModifiersState::SHIFT,
+        "alt" => ModifiersState::ALT,
+        "super" | "cmd" | "meta" => ModifiersState::SUPER,
+        _ => return Err(KeyBindingError::UnknownModifier(part.to_string())),
+      };
+    }
+
+    let key_name = match key.chars().collect::<Vec<_>>()[..] {
+      [c] if c.is_ascii_alphabetic() => format!("Key{}", c.to_ascii_uppercase()),
+      [c] if c.is_ascii_digit() => format!("Digit{c}"),
+      _ => key.to_string(),
+    };
+    let deserializer = StrDeserializer::<serde::de::value::Error>::new(&key_name);
+    let key = KeyCode::deserialize(deserializer).map_err(|_| KeyBindingError::UnknownKey(key.to_string()))?;
+
+    Ok(KeyBinding { key, modifiers })
+  }
+}
+
+impl fmt::Display for KeyBinding {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let names = [
+      (ModifiersState::CONTROL, "Ctrl"),
+      (ModifiersState::SHIFT, "Shift"),
+      (ModifiersState::ALT, "Alt"),
+      (ModifiersState::SUPER, "Super"),
+    ];
+    for (modifier, name) in names {
+      if self.modifiers.contains(modifier) {
+        write!(f, "{name}+")?;
+      }
+    }
+
+    write!(f, "{:?}", self.key)
+  }
+}
+
+/// Maps keys to actions: built-in defaults, then module defaults, then overrides from the `[keys]` config section
+pub struct KeyBindings {
+  bindings: Vec<(KeyBinding, Action)>,
+}
+
+impl KeyBindings {
+  pub fn new(overrides: &BTreeMap<String, String>, module_actions: &[(&'static str, &'static str)]) -> KeyBindings {
+    let builtin = Action::BUILTIN.into_iter();
+    let from_modules = module_actions.iter().map(|&(name, default)| (Action::Module(name), default));
+
+    let mut bindings = Vec::new();
+    for (action, default) in builtin.chain(from_modules) {
+      let binding = overrides.get(action.name()).map(String::as_str).unwrap_or(default);
+      if binding.is_empty() {
+        continue;
+      }
+
+      match binding.parse() {
+        Ok(binding) => bindings.push((binding, action)),
+        Err(e) => warn!("Ignoring key binding {binding:?} for {}: {e}", action.name()),
+      }
+    }
+
+    for name in overrides.keys() {
+      if !bindings.iter().any(|(_, action)| action.name() == name) && !overrides[name].is_empty() {
+        warn!("Key binding for unknown action {name:?} ignored");
+      }
+    }
+
+    KeyBindings { bindings }
+  }
+
+  pub fn action(&self, key: KeyCode, modifiers: ModifiersState) -> Option<Action> {
+    let pressed = KeyBinding { key, modifiers };
+    self.bindings.iter().find(|(binding, _)| *binding == pressed).map(|&(_, action)| action)
+  }
+
+  pub fn log(&self) {
+    info!("Key bindings:");
+    for (binding, action) in &self.bindings {
+      info!("  {binding:<16} {}", action.name());
+    }
+  }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum KeyBindingError {
+  #[error("missing key")]
+  MissingKey,
+
+  #[error("unknown key {0:?}")]
+  UnknownKey(String),
+
+  #[error("unknown modifier {0:?}")]
+  UnknownModifier(String),
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn binding(s: &str) -> KeyBinding {
+    s.parse().unwrap()
+  }
+
+  #[test]
+  fn modifier_combos_round_trip() {
+    let parsed = binding("Ctrl+Shift+S");
+    assert_eq!(parsed.key, KeyCode::KeyS);
+    assert_eq!(parsed.modifiers, ModifiersState::CONTROL | ModifiersState::SHIFT);
+    assert_eq!(parsed.to_string(), "Ctrl+Shift+KeyS");
+
+    for s in ["Escape", "Ctrl+KeyW", "Shift+Alt+F12", "Ctrl+Shift+Alt+Super+Digit1"] {
+      assert_eq!(binding(s).to_string(), s);
+      assert_eq!(binding(&binding(s).to_string()), binding(s));
+    }
+  }
+
+  #[test]
+  fn modifiers_are_case_insensitive_and_order_free() {
+    assert_eq!(binding("shift + control + 1"), binding("Ctrl+Shift+Digit1"));
+    assert_eq!(binding("Cmd+K"), binding("Super+KeyK"));
+  }
+
+  #[test]
+  fn invalid_bindings_are_rejected() {
+    for s in ["", " ", "+", "Ctrl+", "S+"] {
+      assert!(matches!(s.parse::<KeyBinding>(), Err(KeyBindingError::MissingKey)), "{s:?}");
+    }
+    for s in ["Nope", "Ctrl+Nope", "s+", "KeyAA"] {
+      let result = s.parse::<KeyBinding>();
+      assert!(
+        matches!(result, Err(KeyBindingError::UnknownKey(_)) | Err(KeyBindingError::MissingKey)),
+        "{s:?}"
+      );
+    }
+    assert!(matches!("Hyper+S".parse::<KeyBinding>(), Err(KeyBindingError::UnknownModifier(m)) if m == "Hyper"));
+  }
+
+  #[test]
+  fn overrides_replace_and_unbind_defaults() {
+    let overrides = BTreeMap::from([
+      (String::from("screenshot"), String::from("Ctrl+Shift+S")),
+      (String::from("exit"), String::new()),
+    ]);
+    let bindings = KeyBindings::new(&overrides, &[("report", "F3")]);
+
+    let ctrl_shift = ModifiersState::CONTROL | ModifiersState::SHIFT;
+    assert_eq!(bindings.action(KeyCode::KeyS, ctrl_shift), Some(Action::Screenshot));
+    assert_eq!(bindings.action(KeyCode::F12, ModifiersState::empty()), None);
+    assert_eq!(bindings.action(KeyCode::Escape, ModifiersState::empty()), None);
+    assert_eq!(bindings.action(KeyCode::KeyP, ModifiersState::empty()), Some(Action::TogglePause));
+    assert_eq!(bindings.action(KeyCode::KeyP, ModifiersState::SHIFT), None);
+    assert_eq!(bindings.action(KeyCode::F3, ModifiersState::empty()), Some(Action::Module("report")));
+  }
+}
@@ -1,5 +1,12 @@
+pub mod action;
+#[allow(clippy::module_inception)]
 pub mod app;
+pub mod gpu_profiler;
 pub mod gpu_wrapper;
+pub mod headless;
+pub mod input;
 pub mod module;
+pub mod offscreen;
+pub mod scheduler;
 pub mod state;
 pub mod window_wrapper;
@@ -1,5 +1,85 @@
-use winit::window::WindowId;
+use crate::app::{
+  action::{Action, ActionRequest},
+  gpu_profiler::GpuTimings,
+  gpu_wrapper::GpuWrapper,
+  window_wrapper::WindowWrapper,
+};
+use std::time::Duration;
+use wgpu::{CommandEncoder, TextureFormat, TextureView};
+use winit::{dpi::PhysicalSize, event::WindowEvent};
+
+/// Everything a module hook can look at, plus a queue for requesting app actions
+pub struct ModuleContext<'a> {
+  pub gpu: &'a GpuWrapper,
+  /// window the hook was called for, `None` for app-wide hooks
+  pub window: Option<&'a WindowWrapper>,
+  /// rolling GPU time per pass in milliseconds, empty without profiling
+  pub gpu_timings: &'a GpuTimings,
+  /// time since the app started
+  pub elapsed: Duration,
+  actions: &'a mut Vec<ActionRequest>,
+}
+
+impl<'a> ModuleContext<'a> {
+  pub fn new(
+    gpu: &'a GpuWrapper,
+    window: Option<&'a WindowWrapper>,
+    gpu_timings: &'a GpuTimings,
+    elapsed: Duration,
+    actions: &'a mut Vec<ActionRequest>,
+  ) -> ModuleContext<'a> {
+    ModuleContext {
+      gpu,
+      window,
+      gpu_timings,
+      elapsed,
+      actions,
+    }
+  }
+
+  /// Queues an action for `self.window`, it is applied after the current hook dispatch finishes
+  pub fn request(&mut self, action: Action) {
+    let window_id = self.window.map(|window| window.window.id());
+    self.actions.push(ActionRequest { action, window_id });
+  }
+}
 
 pub trait Module {
-  fn on_render(&mut self, window_id: WindowId) {}
+  /// Actions this module handles as `(name, default key binding)`, rebindable through the `[keys]` config section
+  fn actions(&self) -> Vec<(&'static str, &'static str)> {
+    Vec::new()
+  }
+
+  /// Called when one of the actions from `actions` is triggered
+  fn on_action(&mut self, _ctx: &mut ModuleContext, _name: &'static str) {}
+
+  /// Called once the GPU and the first window exist
+  fn on_init(&mut self, _ctx: &mut ModuleContext) {}
+
+  /// Called once per event loop iteration, `dt` is the time since the previous update
+  fn on_update(&mut self, _ctx: &mut ModuleContext, _dt: Duration) {}
+
+  /// Called after the simulation advanced by `steps` fixed steps, skipped while no step ran
+  fn on_compute(&mut self, _ctx: &mut ModuleContext, _steps: u32) {}
+
+  /// Called for every window event before the app handles it
+  fn on_event(&mut self, _ctx: &mut ModuleContext, _event: &WindowEvent) {}
+
+  fn on_resize(&mut self, _ctx: &mut ModuleContext, _new_size: PhysicalSize<u32>) {}
+
+  /// Called before `ctx.window` is rendered
+  fn on_render(&mut self, _ctx: &mut ModuleContext) {}
+
+  /// Adds lines to the HUD of `ctx.window` below the built-in ones, called every frame while the HUD is shown
+  fn on_hud(&mut self, _ctx: &mut ModuleContext, _lines: &mut Vec<String>) {}
+
+  /// Records extra GPU work into the frame of `ctx.window` after the particles were drawn to `view`,
+  /// e.g. HUDs, debug lines or post-processing; passes should load `view` instead of clearing it
+  fn on_draw(&mut self, _ctx: &mut ModuleContext, _encoder: &mut CommandEncoder, _view: &TextureView, _format: TextureFormat) {}
+
+  /// Called right before `ctx.window` is destroyed
+  fn on_window_close(&mut self, _ctx: &mut ModuleContext) {}
+
+  /// Called once when the event loop is exiting
+  fn on_shutdown(&mut self, _ctx: &mut ModuleContext) {}
 }
@@ -0,0 +1,121 @@
+use crate::{
+  app::gpu_wrapper::GpuWrapper,
+  particle_sim::readback::{Readback, ReadbackError},
+};
+use std::{fs::File, io::BufWriter, path::Path};
+use wgpu::{
+  COPY_BYTES_PER_ROW_ALIGNMENT, Extent3d, Origin3d, TexelCopyBufferInfo, TexelCopyBufferLayout, TexelCopyTextureInfo, Texture, TextureAspect,
+  TextureDescriptor, TextureDimension, TextureFormat, TextureUsages, TextureView, TextureViewDescriptor,
+};
+
+/// Render target that lives only on the GPU and can be copied back into a PNG
+pub struct OffscreenTarget {
+  pub texture: Texture,
+  pub view: TextureView,
+}
+
+impl OffscreenTarget {
+  pub fn new(gpu: &GpuWrapper, [width, height]: [u32; 2], format: TextureFormat) -> OffscreenTarget {
+    let texture = gpu.device.create_texture(&TextureDescriptor {
+      label: Some("Offscreen target"),
+      size: Extent3d {
+        width,
+        height,
+        depth_or_array_layers: 1,
+      },
+      mip_level_count: 1,
+      sample_count: 1,
+      dimension: TextureDimension::D2,
+      format,
+      usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::COPY_SRC,
+      view_formats: &[],
+    });
+    let view = texture.create_view(&TextureViewDescriptor::default());
+
+    OffscreenTarget { texture, view }
+  }
+
+  pub fn format(&self) -> TextureFormat {
+    self.texture.format()
+  }
+
+  /// Copies the target back to the CPU as tightly packed rows of 4 byte texels, in the target's channel order
+  pub fn read_pixels(&self, gpu: &GpuWrapper) -> Result<Vec<u8>, OffscreenError> {
+    let Extent3d { width, height, .. } = self.texture.size();
+    let unpadded_bytes_per_row = width * 4;
+    let padded_bytes_per_row = unpadded_bytes_per_row.div_ceil(COPY_BYTES_PER_ROW_ALIGNMENT) * COPY_BYTES_PER_ROW_ALIGNMENT;
+
+    // texture copies need rows aligned to COPY_BYTES_PER_ROW_ALIGNMENT, the padding is cut off afterwards
+    let size = (padded_bytes_per_row * height) as u64;
+    let readback = Readback::<u8>::with_copy(gpu, size, |encoder, staging_buffer| {
+      encoder.copy_texture_to_buffer(
+        TexelCopyTextureInfo {
+          texture: &self.texture,
+          mip_level: 0,
+          origin: Origin3d::ZERO,
+          aspect: TextureAspect::All,
+        },
+        TexelCopyBufferInfo {
+          buffer: staging_buffer,
+          layout: TexelCopyBufferLayout {
+            offset: 0,
+            bytes_per_row: Some(padded_bytes_per_row),
+            rows_per_image: Some(height),
+          },
+        },
+        self.texture.size(),
+      );
+    });
+
+    let padded = readback.wait()?;
+    let pixels = padded
+      .chunks(padded_bytes_per_row as usize)
+      .flat_map(|row| &row[..unpadded_bytes_per_row as usize])
+      .copied()
+      .collect();
+
+    Ok(pixels)
+  }
+
+  pub fn save_png(&self, gpu: &GpuWrapper, path: &Path) -> Result<(), OffscreenError> {
+    let format = self.format();
+    let bgra = match format {
+      TextureFormat::Rgba8Unorm | TextureFormat::Rgba8UnormSrgb => false,
+      TextureFormat::Bgra8Unorm | TextureFormat::Bgra8UnormSrgb => true,
+      _ => return Err(OffscreenError::UnsupportedFormat(format)),
+    };
+
+    let mut pixels = self.read_pixels(gpu)?;
+    if bgra {
+      pixels.chunks_exact_mut(4).for_each(|texel| texel.swap(0, 2));
+    }
+
+    let Extent3d { width, height, .. } = self.texture.size();
+    let file = File::create(path)?;
+    let mut encoder = png::Encoder::new(BufWriter::new(file), width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&pixels)?;
+    writer.finish()?;
+
+    Ok(())
+  }
+}
+
+#[allow(clippy::enum_variant_names)]
+#[derive(thiserror::Error, Debug)]
+pub enum OffscreenError {
+  #[error("Readback error: {0}")]
+  ReadbackError(#[from] ReadbackError),
+
+  #[error("Texture format {0:?} can't be written to PNG")]
+  UnsupportedFormat(TextureFormat),
+
+  #[error("IO error: {0}")]
+  IoError(#[from] std::io::Error),
+
+  #[error("PNG encoding error: {0}")]
+  PngError(#[from] png::EncodingError),
+}
@@ -0,0 +1,232 @@
+use crate::config::TimestepConfig;
+use std::time::Duration;
+use tracing::info;
+
+/// Turns variable frame times into a whole number of fixed-size simulation steps,
+/// leftover time is carried over to the next frame
+pub struct Scheduler {
+  step_dt: f64,
+  max_substeps: u32,
+  max_frame_dt: f64,
+  time_scale: f64,
+  fixed_dt: bool,
+
+  accumulator: f64,
+  sim_time: f64,
+  steps: u64,
+  paused: bool,
+  step_requested: bool,
+  /// pauses once this many steps ran, a replay stops at the state it recorded
+  step_limit: Option<u64>,
+}
+
+/// Simulated seconds covered by a single step, the same for windowed and headless runs.
+/// `time_scale` changes how many steps run per real second, not their size
+pub fn step_dt(config: &TimestepConfig) -> f64 {
+  1.0 / config.tick_rate
+}
+
+impl Scheduler {
+  pub fn new(config: &TimestepConfig) -> Scheduler {
+    Scheduler {
+      step_dt: step_dt(config),
+      max_substeps: config.max_substeps,
+      max_frame_dt: config.max_frame_dt_sec,
+      time_scale: config.time_scale,
+      fixed_dt: config.fixed_dt,
+      accumulator: 0.0,
+      sim_time: 0.0,
+      steps: 0,
+      paused: false,
+      step_requested: false,
+      step_limit: None,
+    }
+  }
+
+  /// Simulated seconds covered by a single step
+  pub fn step_dt(&self) -> f32 {
+    self.step_dt as f32
+  }
+
+  /// Adds the time since the previous frame and returns how many steps to run now
+  pub fn advance(&mut self, frame_dt: Duration) -> u32 {
+    let mut steps = self.steps_for(frame_dt);
+    if let Some(step_limit) = self.step_limit {
+      steps = step_limit.saturating_sub(self.steps).min(steps as u64) as u32;
+      if self.steps + steps as u64 >= step_limit {
+        info!("Paused after reaching the step limit of {step_limit}");
+        self.step_limit = None;
+        self.paused = true;
+        self.accumulator = 0.0;
+      }
+    }
+
+    self.sim_time += steps as f64 * self.step_dt;
+    self.steps += steps as u64;
+    steps
+  }
+
+  fn steps_for(&mut self, frame_dt: Duration) -> u32 {
+    if self.paused {
+      let steps = self.step_requested as u32;
+      self.step_requested = false;
+      return steps;
+    }
+
+    if self.fixed_dt {
+      return 1;
+    }
+
+    // a stall (window drag, breakpoint...) is clamped instead of being caught up on
+    let frame_dt = frame_dt.as_secs_f64().min(self.max_frame_dt);
+    self.accumulator += frame_dt * self.time_scale;
+
+    let steps = ((self.accumulator / self.step_dt) as u32).min(self.max_substeps);
+    self.accumulator -= steps as f64 * self.step_dt;
+    // drop whatever didn't fit into `max_substeps` so the backlog can't snowball
+    self.accumulator = self.accumulator.min(self.step_dt);
+
+    steps
+  }
+
+  /// Runs at most `step_limit` steps in total and pauses once they ran, resuming continues without a limit
+  pub fn set_step_limit(&mut self, step_limit: Option<u64>) {
+    self.step_limit = step_limit;
+  }
+
+  pub fn is_paused(&self) -> bool {
+    self.paused
+  }
+
+  pub fn toggle_pause(&mut self) {
+    self.paused = !self.paused;
+    self.accumulator = 0.0;
+  }
+
+  /// Runs exactly one step on the next `advance` while paused
+  pub fn request_step(&mut self) {
+    self.step_requested = self.paused;
+  }
+
+  /// Simulated seconds since the start
+  pub fn sim_time(&self) -> f64 {
+    self.sim_time
+  }
+
+  /// Steps run since the start, a replay has to run the same number to reach the same state
+  pub fn steps(&self) -> u64 {
+    self.steps
+  }
+
+  pub fn reset(&mut self) {
+    self.accumulator = 0.0;
+    self.sim_time = 0.0;
+    self.steps = 0;
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// quarter second steps, exact in binary so the accumulator math is too
+  fn scheduler(config: TimestepConfig) -> Scheduler {
+    Scheduler::new(&TimestepConfig {
+      tick_rate: 4.0,
+      max_substeps: 100,
+      max_frame_dt_sec: 100.0,
+      ..config
+    })
+  }
+
+  fn secs(secs: f64) -> Duration {
+    Duration::from_secs_f64(secs)
+  }
+
+  #[test]
+  fn leftover_time_carries_over() {
+    let mut scheduler = scheduler(TimestepConfig::default());
+
+    assert_eq!(scheduler.advance(secs(0.375)), 1);
+    assert_eq!(scheduler.advance(secs(0.375)), 2);
+    assert_eq!(scheduler.advance(secs(0.125)), 0);
+    assert_eq!(scheduler.steps(), 3);
+    assert_eq!(scheduler.sim_time(), 0.75);
+  }
+
+  #[test]
+  fn backlog_past_max_substeps_is_dropped() {
+    let mut scheduler = scheduler(TimestepConfig::default());
+    scheduler.max_substeps = 2;
+
+    assert_eq!(scheduler.advance(secs(2.0)), 2);
+    // at most one step is kept for the next frame
+    assert_eq!(scheduler.advance(Duration::ZERO), 1);
+    assert_eq!(scheduler.advance(Duration::ZERO), 0);
+  }
+
+  #[test]
+  fn stall_is_clamped_to_max_frame_dt() {
+    let mut scheduler = scheduler(TimestepConfig::default());
+    scheduler.max_frame_dt = 0.5;
+
+    assert_eq!(scheduler.advance(secs(10.0)), 2);
+    assert_eq!(scheduler.advance(Duration::ZERO), 0);
+  }
+
+  #[test]
+  fn time_scale_runs_more_steps_of_the_same_size() {
+    let mut scheduler = scheduler(TimestepConfig {
+      time_scale: 2.0,
+      ..TimestepConfig::default()
+    });
+
+    assert_eq!(scheduler.advance(secs(0.25)), 2);
+    assert_eq!(scheduler.step_dt(), 0.25);
+    assert_eq!(scheduler.sim_time(), 0.5);
+  }
+
+  #[test]
+  fn fixed_dt_runs_one_step_per_frame() {
+    let mut scheduler = scheduler(TimestepConfig {
+      fixed_dt: true,
+      ..TimestepConfig::default()
+    });
+
+    assert_eq!(scheduler.advance(Duration::ZERO), 1);
+    assert_eq!(scheduler.advance(secs(10.0)), 1);
+  }
+
+  #[test]
+  fn paused_scheduler_only_runs_requested_steps() {
+    let mut scheduler = scheduler(TimestepConfig::default());
+    scheduler.toggle_pause();
+
+    assert_eq!(scheduler.advance(secs(1.0)), 0);
+    scheduler.request_step();
+    assert_eq!(scheduler.advance(secs(1.0)), 1);
+    assert_eq!(scheduler.advance(secs(1.0)), 0);
+
+    scheduler.toggle_pause();
+    // steps are only requested while paused
+    scheduler.request_step();
+    assert_eq!(scheduler.advance(secs(0.25)), 1);
+  }
+
+  #[test]
+  fn step_limit_pauses_at_the_recorded_step() {
+    let mut scheduler = scheduler(TimestepConfig::default());
+    scheduler.max_substeps = 4;
+    scheduler.set_step_limit(Some(5));
+
+    assert_eq!(scheduler.advance(secs(1.0)), 4);
+    assert_eq!(scheduler.advance(secs(1.0)), 1);
+    assert!(scheduler.is_paused());
+    assert_eq!(scheduler.advance(secs(1.0)), 0);
+    assert_eq!(scheduler.steps(), 5);
+
+    // resuming continues without the limit
+    scheduler.toggle_pause();
+    assert_eq!(scheduler.advance(secs(1.0)), 4);
+  }
+}
@@ -1,57 +1,93 @@
 use super::window_wrapper::WindowWrapper;
 use crate::{
-  app::{gpu_wrapper::GpuWrapper, window_wrapper::WindowWrapperError},
-  particle_sim::particle_sim::ParticleSim,
+  app::{
+    action::ActionRequest,
+    gpu_profiler::{GpuProfiler, GpuTimings},
+    gpu_wrapper::{GpuWrapper, GpuWrapperError},
+    module::ModuleContext,
+    offscreen::{OffscreenError, OffscreenTarget},
+    window_wrapper::WindowWrapperError,
+  },
+  config::{Config, GpuConfig, HudConfig, InteractionConfig, SimConfig, WindowConfig},
+  hud::text_pass::TextPass,
+  particle_sim::{
+    compute_pass::ComputePass,
+    obstacle::{Obstacle, ObstacleShape},
+    params::{Interaction, InteractionMode},
+    particle::Particle,
+    particle_sim::ParticleSim,
+    readback::{Readback, ReadbackError},
+    render_pass::RenderTarget,
+    snapshot::{Snapshot, SnapshotError},
+    window::Window,
+  },
 };
-use std::collections::HashMap;
-use tracing::{error, info};
-use wgpu::{
-  CommandEncoderDescriptor, DeviceDescriptor, Instance, InstanceDescriptor, RequestAdapterError, RequestAdapterOptions, RequestDeviceError,
-  TextureViewDescriptor,
+use std::{
+  collections::HashMap,
+  fs,
+  path::{Path, PathBuf},
+  time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use tracing::{error, info, warn};
+use wgpu::{AdapterInfo, CommandEncoder, CommandEncoderDescriptor, SurfaceError, SurfaceTexture, TextureView, TextureViewDescriptor};
+use winit::{
+  dpi::{PhysicalPosition, PhysicalSize},
+  event::{ElementState, MouseButton},
+  event_loop::ActiveEventLoop,
+  window::WindowId,
 };
-use winit::{dpi::PhysicalSize, event_loop::ActiveEventLoop, window::WindowId};
 
 pub struct State {
   gpu: GpuWrapper,
+  gpu_config: GpuConfig,
+  window_config: WindowConfig,
+  sim_config: SimConfig,
+  hud_config: HudConfig,
   windows: HashMap<WindowId, WindowWrapper>,
   sim: Option<ParticleSim>,
+  text_pass: TextPass,
+  /// `None` if the device has no timestamp queries or profiling is turned off
+  profiler: Option<GpuProfiler>,
+
+  /// CPU copy of the particles the simulation is rebuilt from if the device is lost, with the spawned count at the time
+  recovery_particles: Option<(Vec<Particle>, u64)>,
+  recovery_readback: Option<(Readback<Particle>, u64)>,
+  last_recovery_copy: Instant,
 }
 
 impl State {
-  pub async fn new(event_loop: &ActiveEventLoop) -> Result<State, StateError> {
-    let instance_desc = InstanceDescriptor::default();
-    let instance = Instance::new(&instance_desc);
-
-    let adapter_opts = RequestAdapterOptions::default();
-    let adapter = instance.request_adapter(&adapter_opts).await?;
-
-    let device_desc = DeviceDescriptor::default();
-    let (device, queue) = adapter.request_device(&device_desc).await?;
-
-    let gpu = GpuWrapper {
-      instance,
-      adapter,
-      device,
-      queue,
-    };
+  pub async fn new(event_loop: &ActiveEventLoop, config: &Config) -> Result<State, StateError> {
+    let gpu = GpuWrapper::new(&config.gpu).await?;
+    let profiler = GpuProfiler::new(&gpu);
+    let text_pass = TextPass::init(&gpu.device, &gpu.queue);
 
     let mut state = State {
       gpu,
+      gpu_config: config.gpu.clone(),
+      window_config: config.window.clone(),
+      sim_config: config.sim.clone(),
+      hud_config: config.hud.clone(),
       windows: HashMap::new(),
       sim: None,
+      text_pass,
+      profiler,
+      recovery_particles: None,
+      recovery_readback: None,
+      last_recovery_copy: Instant::now(),
     };
 
     let id = state.add_window(event_loop).await?;
-    state.request_redraw(id);
 
     let window = state.windows.get(&id).unwrap();
-    state.sim = Some(ParticleSim::init(&state.gpu, window));
+    let sim = ParticleSim::init(&state.gpu, window.surface_config.format, &config.sim);
+    info!("Boid rules: {:?}", sim.rules());
+    state.sim = Some(sim);
 
     Ok(state)
   }
 
   pub async fn add_window(&mut self, event_loop: &ActiveEventLoop) -> Result<WindowId, StateError> {
-    let window_wrapper = WindowWrapper::new(&self.gpu, event_loop).await?;
+    let window_wrapper = WindowWrapper::new(&self.gpu, event_loop, &self.window_config).await?;
     let key = window_wrapper.window.id();
     info!("Window {key:?} created");
 
@@ -59,8 +95,48 @@ impl State {
     Ok(key)
   }
 
+  pub fn module_context<'a>(&'a self, window_id: Option<WindowId>, elapsed: Duration, actions: &'a mut Vec<ActionRequest>) -> ModuleContext<'a> {
+    let window = window_id.and_then(|id| self.windows.get(&id));
+    ModuleContext::new(&self.gpu, window, self.gpu_timings(), elapsed, actions)
+  }
+
+  /// Rolling GPU milliseconds per pass, empty without profiling
+  pub fn gpu_timings(&self) -> &GpuTimings {
+    gpu_timings(&self.profiler)
+  }
+
+  /// Built-in HUD lines about the simulation: particle count, color and boundary mode and the active boid rules
+  pub fn hud_lines(&self) -> Vec<String> {
+    let Some(sim) = self.sim.as_ref() else {
+      return Vec::new();
+    };
+
+    let rules = sim.rules();
+    vec![
+      format!("Particles: {} ({} species)", sim.particle_count(), self.sim_config.species.len()),
+      format!("Color: {:?}, boundary: {:?}", sim.color_mode(), self.sim_config.boundary.mode),
+      format!(
+        "Max speed: {:.0}, alignment: {:.2} @ {:.0}",
+        rules.max_speed, rules.alignment_strength, rules.alignment_radius
+      ),
+      format!(
+        "Cohesion: {:.2} @ {:.0}, {:.2} @ {:.0}",
+        rules.cohesion_far_strength, rules.cohesion_far_radius, rules.cohesion_close_strength, rules.cohesion_close_radius
+      ),
+      format!("Separation: {:.2} @ {:.0}", rules.separation_strength, rules.separation_radius),
+    ]
+  }
+
+  pub fn adapter_info(&self) -> AdapterInfo {
+    self.gpu.adapter.get_info()
+  }
+
   pub fn has_windows(&self) -> bool {
-    self.windows.len() > 0
+    !self.windows.is_empty()
+  }
+
+  pub fn window_ids(&self) -> Vec<WindowId> {
+    self.windows.keys().copied().collect()
   }
 
   pub fn request_redraw(&self, window_id: WindowId) {
@@ -69,45 +145,416 @@ impl State {
     }
   }
 
+  /// Drops the window along with its surface and view uniform
   pub fn request_close(&mut self, window_id: WindowId) {
     self.windows.remove(&window_id);
+    if let Some(profiler) = self.profiler.as_mut() {
+      profiler.forget(&GpuProfiler::render_label(window_id));
+    }
   }
 
-  pub fn render(&mut self, window_id: WindowId) {
-    match self.windows.get(&window_id) {
-      Some(window_wrapper) => {
-        let Ok(texture) = window_wrapper.surface.get_current_texture() else {
-          error!("Failed to aquire next swapchain texture");
-          return;
-        };
+  /// Draws the particles into the windows of `window_ids`, letting `overlay` record more work into each window's frame,
+  /// and puts the window's `hud_lines` on top. All windows share one encoder and submit.
+  /// Lost or outdated surfaces are reconfigured and skip the frame, only running out of memory is an error,
+  /// the windows acquired before it are still presented
+  pub fn render(
+    &mut self,
+    window_ids: &[WindowId],
+    hud_lines: &HashMap<WindowId, Vec<String>>,
+    mut overlay: impl FnMut(&GpuWrapper, &WindowWrapper, &GpuTimings, &mut CommandEncoder, &TextureView),
+  ) -> Result<(), StateError> {
+    if self.gpu.is_device_lost() {
+      return Ok(());
+    }
 
-        let view = texture.texture.create_view(&TextureViewDescriptor {
-          format: Some(window_wrapper.surface_config.format),
-          ..Default::default()
-        });
+    let mut command_encoder = self.gpu.device.create_command_encoder(&CommandEncoderDescriptor::default());
+    let mut frames = Vec::new();
+    let mut result = Ok(());
 
-        let mut command_encoder = self.gpu.device.create_command_encoder(&CommandEncoderDescriptor::default());
-        if let Some(sim) = self.sim.as_mut() {
-          sim.render(&mut command_encoder, &self.gpu, window_wrapper, &view);
+    for &window_id in window_ids {
+      // closed since its redraw was requested
+      let Some(window_wrapper) = self.windows.get_mut(&window_id) else {
+        continue;
+      };
+      let Some(viewport) = window_wrapper.viewport() else {
+        continue;
+      };
+      let texture = match State::acquire_texture(window_id, window_wrapper, &self.gpu) {
+        Ok(Some(texture)) => texture,
+        Ok(None) => continue,
+        Err(e) => {
+          result = Err(e);
+          break;
         }
+      };
 
-        self.gpu.queue.submit(Some(command_encoder.finish()));
-        texture.present();
+      let format = window_wrapper.surface_config.format;
+      let view = texture.texture.create_view(&TextureViewDescriptor {
+        format: Some(format),
+        ..Default::default()
+      });
+
+      window_wrapper.view.update(&self.gpu.queue, &viewport);
+      if let Some(sim) = self.sim.as_mut() {
+        let label = GpuProfiler::render_label(window_id);
+        let timestamp_writes = self.profiler.as_mut().and_then(|profiler| profiler.render_scope(&label));
+        // windows may use different surface formats, the sim picks a matching pipeline per window
+        sim.render(
+          &mut command_encoder,
+          &self.gpu,
+          &window_wrapper.view,
+          &view,
+          RenderTarget::new(format),
+          timestamp_writes,
+        );
       }
-      None => {
-        let msg = format!("Failed find window with id {:?}", window_id);
-        error!(msg);
+
+      overlay(&self.gpu, window_wrapper, gpu_timings(&self.profiler), &mut command_encoder, &view);
+
+      let lines = hud_lines.get(&window_id).map_or(&[][..], Vec::as_slice);
+      window_wrapper.hud.update(&self.gpu.device, &self.gpu.queue, lines, self.hud_config.scale);
+      self.text_pass.run(
+        &mut command_encoder,
+        &self.gpu.device,
+        &window_wrapper.view,
+        &window_wrapper.hud,
+        &view,
+        RenderTarget::new(format),
+      );
+
+      frames.push(texture);
+    }
+
+    if let Some(profiler) = self.profiler.as_ref() {
+      profiler.resolve(&mut command_encoder);
+    }
+    self.gpu.queue.submit(Some(command_encoder.finish()));
+    for texture in frames {
+      texture.present();
+    }
+    if let Some(profiler) = self.profiler.as_mut() {
+      profiler.end_frame(&self.gpu);
+    }
+
+    result
+  }
+
+  /// Next swapchain texture of the window, `None` if the window has to skip this frame
+  fn acquire_texture(window_id: WindowId, window_wrapper: &WindowWrapper, gpu: &GpuWrapper) -> Result<Option<SurfaceTexture>, StateError> {
+    match window_wrapper.surface.get_current_texture() {
+      Ok(texture) => Ok(Some(texture)),
+      Err(SurfaceError::Lost | SurfaceError::Outdated) => {
+        warn!("Surface of {window_id:?} lost or outdated, reconfiguring");
+        window_wrapper.reconfigure(&gpu.device);
+        Ok(None)
+      }
+      Err(SurfaceError::Timeout) => {
+        warn!("Surface of {window_id:?} timed out, skipping frame");
+        Ok(None)
+      }
+      Err(SurfaceError::OutOfMemory) => Err(StateError::SurfaceError(SurfaceError::OutOfMemory)),
+      Err(e) => {
+        error!("Failed to acquire next swapchain texture: {e}");
+        Ok(None)
       }
     }
   }
 
-  pub fn compute(&mut self) {
-    let mut command_encoder = self.gpu.device.create_command_encoder(&CommandEncoderDescriptor::default());
+  /// Runs `steps` simulation steps of `dt` simulated seconds each
+  pub fn compute(&mut self, steps: u32, dt: f32) {
+    if self.gpu.is_device_lost() || steps == 0 {
+      return;
+    }
+
+    let windows = self.sim_windows();
+    let Some(sim) = self.sim.as_mut() else {
+      return;
+    };
+
+    sim.set_interaction(State::interaction(&self.windows, &self.sim_config.interaction));
+
+    for _ in 0..steps {
+      // one submit per step, so every step sees its own params upload
+      let mut command_encoder = self.gpu.device.create_command_encoder(&CommandEncoderDescriptor::default());
+      let timestamp_writes = self.profiler.as_mut().and_then(|profiler| profiler.compute_scope("compute"));
+      sim.compute(&mut command_encoder, &self.gpu, windows.clone(), dt, timestamp_writes);
+      self.gpu.queue.submit(Some(command_encoder.finish()));
+    }
+
+    self.update_recovery_copy();
+  }
+
+  pub fn cursor_moved(&mut self, window_id: WindowId, position: Option<PhysicalPosition<f64>>) {
+    if let Some(window_wrapper) = self.windows.get_mut(&window_id) {
+      window_wrapper.cursor = position;
+    }
+  }
+
+  pub fn mouse_input(&mut self, window_id: WindowId, state: ElementState, button: MouseButton) {
+    if let Some(window_wrapper) = self.windows.get_mut(&window_id) {
+      window_wrapper.mouse_input(state, button);
+    }
+  }
+
+  /// Forgets held buttons, their release isn't reported once the window lost focus
+  pub fn release_buttons(&mut self, window_id: WindowId) {
+    if let Some(window_wrapper) = self.windows.get_mut(&window_id) {
+      window_wrapper.pressed_buttons.clear();
+    }
+  }
+
+  /// Rectangles of every window sorted by position, so the same window layout always reaches the shader
+  /// in the same order no matter how the window map iterates
+  fn sim_windows(&self) -> Vec<Window> {
+    let mut windows: Vec<Window> = self.windows.values().map(Window::from).collect();
+    windows.sort_by(|a, b| {
+      (a.top_left[1].total_cmp(&b.top_left[1]))
+        .then(a.top_left[0].total_cmp(&b.top_left[0]))
+        .then(a.bottom_right[1].total_cmp(&b.bottom_right[1]))
+        .then(a.bottom_right[0].total_cmp(&b.bottom_right[0]))
+    });
+    windows
+  }
+
+  /// Force at the cursor of the window a mouse button is held over, none if there is no such window
+  fn interaction(windows: &HashMap<WindowId, WindowWrapper>, config: &InteractionConfig) -> Interaction {
+    for window_wrapper in windows.values() {
+      let (Some(&button), Some(pos)) = (window_wrapper.pressed_buttons.last(), window_wrapper.desktop_cursor()) else {
+        continue;
+      };
+
+      let mode = match button {
+        MouseButton::Left => config.left_button,
+        MouseButton::Right => config.right_button,
+        MouseButton::Middle => config.middle_button,
+        _ => InteractionMode::None,
+      };
+      return Interaction::new(pos, mode, config.radius, config.strength);
+    }
+
+    Interaction::none()
+  }
+
+  pub fn is_device_lost(&self) -> bool {
+    self.gpu.is_device_lost()
+  }
+
+  /// Recreates the GPU, every window surface and the simulation after the device was lost,
+  /// particles continue from the latest snapshot or are respawned if there is none
+  pub async fn recover_device(&mut self) -> Result<(), StateError> {
+    warn!("Recreating GPU resources after device loss");
+    self.gpu = GpuWrapper::new(&self.gpu_config).await?;
+    self.profiler = GpuProfiler::new(&self.gpu);
+    self.text_pass = TextPass::init(&self.gpu.device, &self.gpu.queue);
+
+    for window_wrapper in self.windows.values_mut() {
+      window_wrapper.recreate_surface(&self.gpu)?;
+    }
+
+    // the pending copy belongs to the lost device
+    self.recovery_readback = None;
+    self.rebuild_sim(self.recovery_particles.clone());
+
+    info!("GPU resources recreated on {}", self.gpu.adapter.get_info().name);
+    Ok(())
+  }
+
+  /// Recreates the simulation with the current rules and color mode, continuing from `particles` and their spawned count if given
+  fn rebuild_sim(&mut self, particles: Option<(Vec<Particle>, u64)>) {
+    let Some(format) = self.windows.values().next().map(|window| window.surface_config.format) else {
+      return;
+    };
+
+    let mut sim_config = self.sim_config.clone();
+    let color_mode = self.sim.as_ref().map(|sim| {
+      sim_config.rules = *sim.rules();
+      sim.color_mode()
+    });
+
+    let mut sim = match particles {
+      Some((particles, spawned)) => ParticleSim::init_with_particles(&self.gpu, format, &sim_config, particles, spawned),
+      None => ParticleSim::init(&self.gpu, format, &sim_config),
+    };
+    if let Some(color_mode) = color_mode {
+      sim.set_color_mode(&self.gpu, color_mode);
+    }
+    self.sim = Some(sim);
+  }
+
+  /// Collects the pending particle copy and starts a new one every `snapshot_interval_sec`, without stalling the frame
+  fn update_recovery_copy(&mut self) {
+    if let Some((readback, spawned)) = self.recovery_readback.as_mut()
+      && let Some(result) = readback.try_take()
+    {
+      match result {
+        Ok(particles) => self.recovery_particles = Some((particles, *spawned)),
+        Err(e) => warn!("Failed to copy particles back for device loss recovery: {e}"),
+      }
+      self.recovery_readback = None;
+    }
+
+    let due = self.last_recovery_copy.elapsed().as_secs_f64() >= self.gpu_config.snapshot_interval_sec;
+    if let Some(sim) = self.sim.as_ref()
+      && due
+      && self.recovery_readback.is_none()
+    {
+      self.recovery_readback = Some((sim.readback(&self.gpu), sim.spawned()));
+      self.last_recovery_copy = Instant::now();
+    }
+  }
+
+  /// Saves the current simulation state, blocking until the particles are copied back
+  pub fn save_snapshot(&self, path: &Path, dt: f32) -> Result<(), StateError> {
+    let Some(sim) = self.sim.as_ref() else {
+      return Ok(());
+    };
+
+    let windows = self.sim_windows();
+    let particles = sim.read_particles(&self.gpu)?;
+    let snapshot = Snapshot {
+      seed: self.sim_config.seed.unwrap_or_default(),
+      spawned: sim.spawned(),
+      params: sim.params(&windows, dt),
+      windows,
+      obstacles: sim.obstacles().to_vec(),
+      particles,
+    };
+    snapshot.save(path)?;
+
+    info!("Snapshot of {} particles saved to {}", snapshot.particles.len(), path.display());
+    Ok(())
+  }
+
+  /// Continues the simulation from a saved snapshot. Window rectangles can't be restored, the windows stay where they are
+  pub fn load_snapshot(&mut self, path: &Path, dt: f32) -> Result<(), StateError> {
+    let snapshot = Snapshot::load(path)?;
+    // the particle buffers would clamp the count and no longer fit the snapshot
+    snapshot.check_particle_count(ComputePass::max_particles(&self.gpu.device))?;
+    if snapshot.params.dt != dt {
+      warn!("Snapshot was saved with a step of {}s, continuing with {dt}s", snapshot.params.dt);
+    }
+
+    self.sim_config.seed = Some(snapshot.seed);
+    self.sim_config.rules = snapshot.params.rules;
+    self.sim_config.particle_count = snapshot.particles.len() as u32;
+    self.sim_config.obstacles = snapshot.obstacles.iter().map(ObstacleShape::from).collect();
+
+    match self.sim.as_mut() {
+      Some(sim) => {
+        sim.set_particle_count(&self.gpu, snapshot.particles.len() as u32);
+        sim.set_particles(&self.gpu, &snapshot.particles);
+        sim.restore_rng(&self.sim_config, snapshot.spawned);
+        sim.set_rules(&self.gpu.queue, snapshot.params.rules);
+        sim.set_obstacles(&self.gpu.queue, &self.sim_config.obstacles);
+      }
+      None => self.rebuild_sim(Some((snapshot.particles.clone(), snapshot.spawned))),
+    }
     if let Some(sim) = self.sim.as_mut() {
-      sim.compute(&mut command_encoder, &self.gpu, self.windows.values().collect());
+      sim.set_step_index(snapshot.params.step_index);
     }
+    self.recovery_particles = Some((snapshot.particles, snapshot.spawned));
+
+    info!("Snapshot loaded from {} (seed {})", path.display(), snapshot.seed);
+    Ok(())
+  }
+
+  /// Adds `delta` particles, or removes them when negative
+  pub fn change_particle_count(&mut self, delta: i64) {
+    let Some(sim) = self.sim.as_mut() else {
+      return;
+    };
+
+    let requested = (sim.particle_count() as i64 + delta).clamp(1, u32::MAX as i64) as u32;
+    let count = sim.set_particle_count(&self.gpu, requested);
+    if count != requested {
+      warn!("Particle count limited to {count} by the device");
+    }
+
+    self.sim_config.particle_count = count;
+    info!("Particle count: {count}");
+  }
 
+  /// Respawns every particle from the sim config
+  pub fn reset(&mut self) {
+    if let Some(sim) = self.sim.as_mut() {
+      sim.reset(&self.gpu, &self.sim_config);
+      info!("Simulation reset");
+    }
+  }
+
+  pub fn cycle_color_mode(&mut self) {
+    if let Some(sim) = self.sim.as_mut() {
+      let color_mode = sim.cycle_color_mode(&self.gpu);
+      info!("Color mode: {color_mode:?}");
+    }
+  }
+
+  /// Places a circle obstacle at the cursor of the window, or removes the obstacle under it
+  pub fn edit_obstacles(&mut self, window_id: WindowId, place: bool) {
+    let Some(cursor) = self.windows.get(&window_id).and_then(WindowWrapper::desktop_cursor) else {
+      return;
+    };
+
+    let obstacles = &mut self.sim_config.obstacles;
+    if place {
+      if obstacles.len() >= self.sim_config.max_obstacles as usize {
+        warn!("Can't place more than {} obstacles", self.sim_config.max_obstacles);
+        return;
+      }
+
+      obstacles.push(ObstacleShape::Circle {
+        center: cursor,
+        radius: self.sim_config.placed_obstacle_radius,
+      });
+    } else {
+      // the innermost one if obstacles overlap
+      let under_cursor = obstacles
+        .iter()
+        .map(|shape| Obstacle::from(shape).sdf(cursor))
+        .enumerate()
+        .filter(|&(_, d)| d <= 0.0)
+        .min_by(|(_, a), (_, b)| a.total_cmp(b));
+      let Some((index, _)) = under_cursor else {
+        return;
+      };
+
+      obstacles.remove(index);
+    }
+
+    if let Some(sim) = self.sim.as_mut() {
+      sim.set_obstacles(&self.gpu.queue, &self.sim_config.obstacles);
+    }
+    info!("Obstacles: {}", self.sim_config.obstacles.len());
+  }
+
+  /// Draws the particles of the window into an offscreen target and saves it as a PNG in `dir`
+  pub fn screenshot(&mut self, window_id: WindowId, dir: &Path) -> Result<PathBuf, StateError> {
+    let window_wrapper = self.windows.get(&window_id).ok_or(StateError::WindowNotFoundError(window_id))?;
+    let (Some(viewport), Some(sim)) = (window_wrapper.viewport(), self.sim.as_mut()) else {
+      return Err(StateError::WindowNotFoundError(window_id));
+    };
+
+    let config = &window_wrapper.surface_config;
+    let target = OffscreenTarget::new(&self.gpu, [config.width, config.height], config.format);
+
+    window_wrapper.view.update(&self.gpu.queue, &viewport);
+    let mut command_encoder = self.gpu.device.create_command_encoder(&CommandEncoderDescriptor::default());
+    sim.render(
+      &mut command_encoder,
+      &self.gpu,
+      &window_wrapper.view,
+      &target.view,
+      RenderTarget::new(target.format()),
+      None,
+    );
     self.gpu.queue.submit(Some(command_encoder.finish()));
+
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis();
+    fs::create_dir_all(dir)?;
+    let path = dir.join(format!("screenshot_{timestamp}.png"));
+    target.save_png(&self.gpu, &path)?;
+
+    Ok(path)
   }
 
   pub fn resize(&mut self, window_id: WindowId, new_size: PhysicalSize<u32>) {
@@ -121,14 +568,35 @@ impl State {
   }
 }
 
+#[allow(clippy::enum_variant_names)]
 #[derive(thiserror::Error, Debug)]
 pub enum StateError {
-  #[error("Request adapter error: {0}")]
-  RequestAdapterError(#[from] RequestAdapterError),
-
-  #[error("Request device error: {0}")]
-  RequestDeviceError(#[from] RequestDeviceError),
+  #[error("GPU error: {0}")]
+  GpuError(#[from] GpuWrapperError),
 
   #[error("App window error: {0}")]
   AppWindowError(#[from] WindowWrapperError),
+
+  #[error("Offscreen error: {0}")]
+  OffscreenError(#[from] OffscreenError),
+
+  #[error("IO error: {0}")]
+  IoError(#[from] std::io::Error),
+
+  #[error("Readback error: {0}")]
+  ReadbackError(#[from] ReadbackError),
+
+  #[error("Snapshot error: {0}")]
+  SnapshotError(#[from] SnapshotError),
+
+  #[error("Surface error: {0}")]
+  SurfaceError(SurfaceError),
+
+  #[error("Window {0:?} not found")]
+  WindowNotFoundError(WindowId),
+}
+
+fn gpu_timings(profiler: &Option<GpuProfiler>) -> &GpuTimings {
+  static NO_TIMINGS: GpuTimings = GpuTimings::new();
+  profiler.as_ref().map_or(&NO_TIMINGS, GpuProfiler::timings)
 }
@@ -1,9 +1,15 @@
-use crate::app::gpu_wrapper::GpuWrapper;
+use crate::{
+  app::gpu_wrapper::GpuWrapper,
+  config::WindowConfig,
+  hud::text_pass::HudText,
+  particle_sim::{view::WindowView, window::Window as Viewport},
+};
 use std::sync::Arc;
-use wgpu::{CompositeAlphaMode, CreateSurfaceError, Device, PresentMode, Surface, SurfaceConfiguration, TextureFormat, TextureUsages};
+use wgpu::{CompositeAlphaMode, CreateSurfaceError, Device, Surface, SurfaceConfiguration, TextureFormat, TextureUsages};
 use winit::{
-  dpi::PhysicalSize,
+  dpi::{PhysicalPosition, PhysicalSize},
   error::OsError,
+  event::{ElementState, MouseButton},
   event_loop::ActiveEventLoop,
   window::{Window, WindowAttributes},
 };
@@ -12,23 +18,28 @@ pub struct WindowWrapper {
   pub window: Arc<Window>,
   pub surface: Surface<'static>,
   pub surface_config: SurfaceConfiguration,
+  /// desktop-space rectangle the window shows, updated before every frame
+  pub view: WindowView,
+  /// HUD lines shown over the window, updated before every frame
+  pub hud: HudText,
+  /// cursor position inside the window, `None` while the cursor is outside
+  pub cursor: Option<PhysicalPosition<f64>>,
+  /// mouse buttons held down over the window, the latest press last
+  pub pressed_buttons: Vec<MouseButton>,
 }
 
 impl WindowWrapper {
-  pub async fn new(gpu: &GpuWrapper, event_loop: &ActiveEventLoop) -> Result<WindowWrapper, WindowWrapperError> {
-    let window_attr = WindowAttributes::default();
+  pub async fn new(gpu: &GpuWrapper, event_loop: &ActiveEventLoop, config: &WindowConfig) -> Result<WindowWrapper, WindowWrapperError> {
+    let mut window_attr = WindowAttributes::default().with_title(config.title.clone());
+    if let Some([width, height]) = config.size {
+      window_attr = window_attr.with_inner_size(PhysicalSize::new(width, height));
+    }
 
     let window = event_loop.create_window(window_attr)?;
     let window = Arc::new(window);
 
     let surface = gpu.instance.create_surface(window.clone())?;
-
-    let format = surface
-      .get_capabilities(&gpu.adapter)
-      .formats
-      .into_iter()
-      .next()
-      .unwrap_or(TextureFormat::Bgra8Unorm);
+    let format = WindowWrapper::preferred_format(gpu, &surface);
 
     let surface_config = SurfaceConfiguration {
       usage: TextureUsages::RENDER_ATTACHMENT,
@@ -38,7 +49,7 @@ impl WindowWrapper {
       width: window.inner_size().width,
       height: window.inner_size().height,
       desired_maximum_frame_latency: 2,
-      present_mode: PresentMode::AutoVsync,
+      present_mode: config.present_mode,
     };
 
     surface.configure(&gpu.device, &surface_config);
@@ -47,6 +58,10 @@ impl WindowWrapper {
       window,
       surface,
       surface_config,
+      view: WindowView::new(&gpu.device),
+      hud: HudText::new(&gpu.device),
+      cursor: None,
+      pressed_buttons: Vec::new(),
     })
   }
 
@@ -55,8 +70,56 @@ impl WindowWrapper {
     self.surface_config.width = width;
     self.surface_config.height = height;
 
+    self.reconfigure(device);
+  }
+
+  /// Applies the surface config again, needed after the surface was lost or became outdated
+  pub fn reconfigure(&self, device: &Device) {
     self.surface.configure(device, &self.surface_config);
   }
+
+  /// Creates a new surface, view and HUD for the window on a new GPU, the old ones belong to a lost device
+  pub fn recreate_surface(&mut self, gpu: &GpuWrapper) -> Result<(), WindowWrapperError> {
+    self.surface = gpu.instance.create_surface(self.window.clone())?;
+    self.view = WindowView::new(&gpu.device);
+    self.hud = HudText::new(&gpu.device);
+    self.surface_config.format = WindowWrapper::preferred_format(gpu, &self.surface);
+
+    self.reconfigure(&gpu.device);
+    Ok(())
+  }
+
+  fn preferred_format(gpu: &GpuWrapper, surface: &Surface) -> TextureFormat {
+    surface
+      .get_capabilities(&gpu.adapter)
+      .formats
+      .into_iter()
+      .next()
+      .unwrap_or(TextureFormat::Bgra8Unorm)
+  }
+
+  pub fn mouse_input(&mut self, state: ElementState, button: MouseButton) {
+    self.pressed_buttons.retain(|&pressed| pressed != button);
+    if state.is_pressed() {
+      self.pressed_buttons.push(button);
+    }
+  }
+
+  /// Cursor in the desktop space the simulation runs in
+  pub fn desktop_cursor(&self) -> Option<[f32; 2]> {
+    let cursor = self.cursor?;
+    let viewport = self.viewport()?;
+
+    Some([viewport.top_left[0] + cursor.x as f32, viewport.top_left[1] + cursor.y as f32])
+  }
+
+  /// Desktop-space rectangle covered by the window, `None` if the platform can't report its position
+  pub fn viewport(&self) -> Option<Viewport> {
+    let pos = self.window.inner_position().ok()?;
+    let size = self.window.inner_size();
+
+    Some(Viewport::new([pos.x as f32, pos.y as f32], size.into()))
+  }
 }
 
 #[derive(thiserror::Error, Debug)]
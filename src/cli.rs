@@ -0,0 +1,136 @@
+use crate::config::{Config, DEFAULT_CONFIG_PATH};
+use clap::{Parser, ValueEnum};
+use std::path::PathBuf;
+use tracing_subscriber::EnvFilter;
+use wgpu::PowerPreference;
+
+/// Boids simulation spread across desktop windows
+#[derive(Parser, Debug)]
+#[command(version, about)]
+pub struct Cli {
+  /// Config file to load, defaults are used if it doesn't exist
+  #[arg(short, long, default_value = DEFAULT_CONFIG_PATH)]
+  pub config: PathBuf,
+
+  /// Number of particles, overrides sim.particle_count
+  #[arg(short, long, conflicts_with = "replay")]
+  pub particles: Option<u32>,
+
+  /// Seed for the initial particle state, overrides sim.seed
+  #[arg(short, long, conflicts_with = "replay")]
+  pub seed: Option<u64>,
+
+  /// Run exactly one simulation step per frame, overrides sim.timestep.fixed_dt
+  #[arg(long)]
+  pub fixed_dt: bool,
+
+  /// Write a run manifest (seed, config, adapter) to this file, for replaying the run later
+  #[arg(long, value_name = "PATH")]
+  pub manifest: Option<PathBuf>,
+
+  /// Replay a run: use the config recorded in this manifest instead of the config file,
+  /// its seed and particle count can't be overridden
+  #[arg(long, value_name = "PATH", conflicts_with = "config")]
+  pub replay: Option<PathBuf>,
+
+  /// Continue from a snapshot saved with the save_snapshot action or --save-snapshot
+  #[arg(long, value_name = "PATH")]
+  pub load_snapshot: Option<PathBuf>,
+
+  /// Comma separated wgpu backends, e.g. "vulkan,gl"; overrides gpu.backends
+  #[arg(long)]
+  pub backend: Option<String>,
+
+  /// Adapter power preference, overrides gpu.power_preference
+  #[arg(long, value_enum)]
+  pub power_preference: Option<PowerPreferenceArg>,
+
+  /// Only accept a fallback (software) adapter
+  #[arg(long)]
+  pub force_fallback_adapter: bool,
+
+  /// Windowing platform to use
+  #[arg(long, value_enum, default_value_t = Platform::X11)]
+  pub platform: Platform,
+
+  /// Log filter in tracing-subscriber EnvFilter syntax, e.g. "info" or "learn_wgpu=debug"; RUST_LOG is used when not set
+  #[arg(short, long, value_parser = parse_log_filter)]
+  pub log_level: Option<String>,
+
+  /// Run without windows for the given number of steps and write the result to PNG.
+  /// The step count defaults to the recorded one with --replay, and to 1 otherwise
+  #[arg(long, value_name = "STEPS", num_args = 0..=1)]
+  pub headless: Option<Option<u32>>,
+
+  /// Directory headless frames are written to
+  #[arg(long, default_value = "headless_output", requires = "headless")]
+  pub output: PathBuf,
+
+  /// Write every headless frame instead of only the last one
+  #[arg(long, requires = "headless")]
+  pub save_every_frame: bool,
+
+  /// Save a snapshot of the simulation state once the headless run finishes
+  #[arg(long, value_name = "PATH", requires = "headless")]
+  pub save_snapshot: Option<PathBuf>,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum PowerPreferenceArg {
+  None,
+  LowPower,
+  HighPerformance,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Platform {
+  /// Let winit pick the platform
+  Auto,
+  X11,
+  Wayland,
+}
+
+impl Cli {
+  /// Applies command line overrides on top of the loaded config
+  pub fn apply(&self, config: &mut Config) {
+    if let Some(particles) = self.particles {
+      config.sim.particle_count = particles;
+    }
+
+    if let Some(seed) = self.seed {
+      config.sim.seed = Some(seed);
+    }
+
+    if self.fixed_dt {
+      config.sim.timestep.fixed_dt = true;
+    }
+
+    if let Some(backend) = &self.backend {
+      config.gpu.backends = Some(backend.clone());
+    }
+
+    if let Some(power_preference) = self.power_preference {
+      config.gpu.power_preference = power_preference.into();
+    }
+
+    if self.force_fallback_adapter {
+      config.gpu.force_fallback_adapter = true;
+    }
+  }
+}
+
+/// Keeps the filter as text, `EnvFilter` can't be cloned like clap values have to be
+fn parse_log_filter(filter: &str) -> Result<String, String> {
+  EnvFilter::try_new(filter).map_err(|e| e.to_string())?;
+  Ok(filter.to_string())
+}
+
+impl From<PowerPreferenceArg> for PowerPreference {
+  fn from(arg: PowerPreferenceArg) -> Self {
+    match arg {
+      PowerPreferenceArg::None => PowerPreference::None,
+      PowerPreferenceArg::LowPower => PowerPreference::LowPower,
+      PowerPreferenceArg::HighPerformance => PowerPreference::HighPerformance,
+    }
+  }
+}
@@ -0,0 +1,592 @@
+use crate::{
+  app::input::{KeyBinding, KeyBindingError},
+  particle_sim::{
+    obstacle::{MAX_POLYGON_POINTS, ObstacleShape},
+    params::{BoidRules, BoundaryMode, InteractionMode},
+  },
+};
+use serde::{Deserialize, Serialize};
+use std::{
+  collections::BTreeMap,
+  fs,
+  path::{Path, PathBuf},
+};
+use tracing::{info, warn};
+use wgpu::{PowerPreference, PresentMode};
+
+pub const DEFAULT_CONFIG_PATH: &str = "config.toml";
+
+/// Startup settings, every section falls back to its defaults when missing from the file
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+  pub sim: SimConfig,
+  pub window: WindowConfig,
+  pub gpu: GpuConfig,
+  pub fps: FpsConfig,
+  pub hud: HudConfig,
+  /// action name to key binding, e.g. `screenshot = "Ctrl+S"`; an empty string unbinds the action
+  pub keys: BTreeMap<String, String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SimConfig {
+  pub particle_count: u32,
+  /// particles added or removed by the add_particles and remove_particles actions
+  pub particle_step: u32,
+  /// seed for the initial particle state, a random one is used when not set
+  pub seed: Option<u64>,
+  /// size of the triangle drawn for each particle, in pixels
+  pub triangle_size: f32,
+  /// deprecated and ignored, the windows buffer grows with the open windows up to the device limit
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub max_windows: Option<u32>,
+  /// number of obstacles the obstacle buffer has room for
+  pub max_obstacles: u32,
+  /// obstacles in desktop coordinates, the place_obstacle and remove_obstacle actions edit this list at runtime
+  pub obstacles: Vec<ObstacleShape>,
+  /// radius of the circles the place_obstacle action puts at the cursor
+  pub placed_obstacle_radius: f32,
+  /// file the save_snapshot and load_snapshot actions use
+  pub snapshot_path: PathBuf,
+  pub timestep: TimestepConfig,
+  pub spawn: SpawnConfig,
+  pub boundary: BoundaryConfig,
+  pub interaction: InteractionConfig,
+  pub rules: BoidRules,
+  /// at least one species, each particle belongs to one of them
+  pub species: Vec<SpeciesConfig>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TimestepConfig {
+  /// simulation steps per simulated second, each step advances by `1 / tick_rate`
+  pub tick_rate: f64,
+  /// most steps run in a single frame, time beyond that is dropped
+  pub max_substeps: u32,
+  /// frame times above this are clamped, so a stall doesn't turn into a burst of steps
+  pub max_frame_dt_sec: f64,
+  /// simulated seconds per real second
+  pub time_scale: f64,
+  /// runs exactly one step per frame no matter how long the frame took, for reproducible runs
+  pub fixed_dt: bool,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SpawnConfig {
+  /// particles spawn uniformly inside the desktop-space rectangle `pos_min..pos_max`
+  pub pos_min: [f32; 2],
+  pub pos_max: [f32; 2],
+  /// each velocity component is picked from `-max_velocity..max_velocity`
+  pub max_velocity: f32,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BoundaryConfig {
+  pub mode: BoundaryMode,
+  /// desktop-space rectangle `world_min..world_max` the wrap mode wraps around,
+  /// the bounding box of all windows when not set
+  pub world_min: Option<[f32; 2]>,
+  pub world_max: Option<[f32; 2]>,
+  /// distance over which the edges of neighbouring windows are blended into one rounded boundary, 0 keeps sharp corners
+  pub smoothing: f32,
+}
+
+/// One species of the ecosystem, unset values fall back to `sim.rules`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SpeciesConfig {
+  pub name: String,
+  /// relative share of spawned particles
+  pub share: f32,
+  /// color particles spawn with, a random one per particle when not set
+  pub color: Option<[f32; 4]>,
+  pub max_speed: Option<f32>,
+  pub alignment_radius: Option<f32>,
+  /// replaces `cohesion_far_radius`
+  pub cohesion_radius: Option<f32>,
+  pub separation_radius: Option<f32>,
+  /// weights towards every species in table order, negative values turn a force around; all 1 when empty
+  pub alignment: Vec<f32>,
+  pub cohesion: Vec<f32>,
+  pub separation: Vec<f32>,
+}
+
+/// Forces applied around the cursor while a mouse button is held over a window
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct InteractionConfig {
+  /// distance from the cursor at which the force has faded out, in pixels
+  pub radius: f32,
+  /// force at the cursor, it falls off linearly towards `radius`
+  pub strength: f32,
+  pub left_button: InteractionMode,
+  pub right_button: InteractionMode,
+  pub middle_button: InteractionMode,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WindowConfig {
+  pub title: String,
+  /// inner size of new windows, the platform default when not set
+  pub size: Option<[u32; 2]>,
+  pub present_mode: PresentMode,
+  /// directory screenshots of windows are saved to
+  pub screenshot_dir: PathBuf,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GpuConfig {
+  pub power_preference: PowerPreference,
+  /// comma separated backend names, e.g. "vulkan,gl"; every backend when not set
+  pub backends: Option<String>,
+  pub force_fallback_adapter: bool,
+  /// how often particles are copied back to the CPU so the simulation survives a lost GPU device
+  pub snapshot_interval_sec: f64,
+  /// measures GPU time of the compute and render passes if the adapter supports timestamp queries
+  pub profile: bool,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FpsConfig {
+  /// how often the stats of every window are logged
+  pub cooldown_sec: f64,
+  /// number of recent frames the min/avg/p95/p99 frame times are taken over
+  pub sample_frames: usize,
+  /// a frame taking this many times the average frame time is reported as a hitch
+  pub hitch_factor: f64,
+  /// file the logged stats are also written to, one row per window every `cooldown_sec`
+  pub output: Option<PathBuf>,
+  pub output_format: TimeSeriesFormat,
+  /// exits after this many seconds with a last report, for benchmark runs of a fixed length
+  pub run_for_sec: Option<f64>,
+}
+
+/// Text panel drawn in the top left corner of every window
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HudConfig {
+  /// whether the HUD is shown at startup, the toggle_hud action shows or hides it at runtime
+  pub enabled: bool,
+  /// window pixels per font pixel, the font is 8 pixels high
+  pub scale: u32,
+}
+
+/// Row format of the `fps.output` file
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TimeSeriesFormat {
+  /// comma separated values with a header row
+  #[default]
+  Csv,
+  /// one JSON object per line
+  Jsonl,
+}
+
+impl Config {
+  /// Reads the config at `path`, using defaults if the file doesn't exist
+  pub fn load_or_default(path: &Path) -> Result<Config, ConfigError> {
+    if !path.exists() {
+      info!("Config file {} not found, using defaults", path.display());
+      return Ok(Config::default());
+    }
+
+    Config::load(path)
+  }
+
+  pub fn load(path: &Path) -> Result<Config, ConfigError> {
+    let text = fs::read_to_string(path).map_err(|source| ConfigError::ReadError {
+      path: path.to_path_buf(),
+      source,
+    })?;
+
+    let config: Config = toml::from_str(&text)?;
+    if config.sim.max_windows.is_some() {
+      warn!("sim.max_windows is deprecated and ignored, the simulation holds as many windows as the device can bind");
+    }
+
+    info!("Config loaded from {}", path.display());
+    Ok(config)
+  }
+
+  pub fn validate(&self) -> Result<(), ConfigError> {
+    let sim = &self.sim;
+    ensure(sim.particle_count > 0, "sim.particle_count", "must be greater than 0")?;
+    ensure(sim.particle_step > 0, "sim.particle_step", "must be greater than 0")?;
+    ensure(sim.triangle_size > 0.0, "sim.triangle_size", "must be greater than 0")?;
+    ensure(sim.max_obstacles > 0, "sim.max_obstacles", "must be greater than 0")?;
+    ensure(
+      sim.obstacles.len() <= sim.max_obstacles as usize,
+      "sim.obstacles",
+      "can't hold more than max_obstacles obstacles",
+    )?;
+    ensure(
+      sim.obstacles.iter().all(valid_obstacle),
+      "sim.obstacles",
+      "every obstacle needs a positive size",
+    )?;
+    ensure(
+      sim.obstacles.iter().all(|shape| match shape {
+        ObstacleShape::Polygon { points } => (3..=MAX_POLYGON_POINTS).contains(&points.len()),
+        _ => true,
+      }),
+      "sim.obstacles",
+      "polygons need 3 to 16 points",
+    )?;
+    ensure(sim.placed_obstacle_radius > 0.0, "sim.placed_obstacle_radius", "must be greater than 0")?;
+
+    let timestep = &sim.timestep;
+    ensure(timestep.tick_rate > 0.0, "sim.timestep.tick_rate", "must be greater than 0")?;
+    ensure(timestep.max_substeps > 0, "sim.timestep.max_substeps", "must be greater than 0")?;
+    ensure(timestep.max_frame_dt_sec > 0.0, "sim.timestep.max_frame_dt_sec", "must be greater than 0")?;
+    ensure(timestep.time_scale >= 0.0, "sim.timestep.time_scale", "can't be negative")?;
+
+    let spawn = &sim.spawn;
+    ensure(
+      spawn.pos_min[0] < spawn.pos_max[0] && spawn.pos_min[1] < spawn.pos_max[1],
+      "sim.spawn",
+      "pos_min must be smaller than pos_max on both axes",
+    )?;
+    ensure(spawn.max_velocity > 0.0, "sim.spawn.max_velocity", "must be greater than 0")?;
+
+    let boundary = &sim.boundary;
+    ensure(
+      boundary.world_min.is_some() == boundary.world_max.is_some(),
+      "sim.boundary",
+      "world_min and world_max have to be set together",
+    )?;
+    ensure(boundary.smoothing >= 0.0, "sim.boundary.smoothing", "can't be negative")?;
+    if let (Some(min), Some(max)) = (boundary.world_min, boundary.world_max) {
+      ensure(
+        min[0] < max[0] && min[1] < max[1],
+        "sim.boundary",
+        "world_min must be smaller than world_max on both axes",
+      )?;
+    }
+
+    let interaction = &sim.interaction;
+    ensure(interaction.radius > 0.0, "sim.interaction.radius", "must be greater than 0")?;
+    ensure(interaction.strength >= 0.0, "sim.interaction.strength", "can't be negative")?;
+
+    let rules = &sim.rules;
+    ensure(rules.max_speed > 0.0, "sim.rules.max_speed", "must be greater than 0")?;
+    let radii = [
+      rules.alignment_radius,
+      rules.cohesion_far_radius,
+      rules.cohesion_close_radius,
+      rules.separation_radius,
+      rules.xenophobia_start_radius,
+      rules.xenophobia_end_radius,
+    ];
+    ensure(radii.iter().all(|&r| r >= 0.0), "sim.rules", "radii can't be negative")?;
+    ensure(
+      rules.xenophobia_start_radius <= rules.xenophobia_end_radius,
+      "sim.rules.xenophobia_start_radius",
+      "can't be larger than xenophobia_end_radius",
+    )?;
+    ensure(
+      (-1.0..=1.0).contains(&rules.contagion_min_cos),
+      "sim.rules.contagion_min_cos",
+      "must be within -1..=1",
+    )?;
+    ensure(rules.obstacle_strength >= 0.0, "sim.rules.obstacle_strength", "can't be negative")?;
+    ensure(rules.obstacle_distance > 0.0, "sim.rules.obstacle_distance", "must be greater than 0")?;
+
+    ensure(!sim.species.is_empty(), "sim.species", "needs at least one species")?;
+    for species in &sim.species {
+      ensure(species.share > 0.0, "sim.species.share", "must be greater than 0")?;
+      if let Some(max_speed) = species.max_speed {
+        ensure(max_speed > 0.0, "sim.species.max_speed", "must be greater than 0")?;
+      }
+      let radii = [species.alignment_radius, species.cohesion_radius, species.separation_radius];
+      ensure(radii.iter().flatten().all(|&r| r >= 0.0), "sim.species", "radii can't be negative")?;
+      let rows = [&species.alignment, &species.cohesion, &species.separation];
+      ensure(
+        rows.iter().all(|row| row.is_empty() || row.len() == sim.species.len()),
+        "sim.species",
+        "weight rows need one entry per species",
+      )?;
+    }
+
+    if let Some([width, height]) = self.window.size {
+      ensure(width > 0 && height > 0, "window.size", "must be greater than 0 on both axes")?;
+    }
+
+    ensure(
+      self.gpu.snapshot_interval_sec > 0.0,
+      "gpu.snapshot_interval_sec",
+      "must be greater than 0",
+    )?;
+    ensure(self.fps.cooldown_sec > 0.0, "fps.cooldown_sec", "must be greater than 0")?;
+    ensure(self.fps.sample_frames > 0, "fps.sample_frames", "must be greater than 0")?;
+    ensure(self.fps.hitch_factor > 1.0, "fps.hitch_factor", "must be greater than 1")?;
+    if let Some(run_for_sec) = self.fps.run_for_sec {
+      ensure(run_for_sec > 0.0, "fps.run_for_sec", "must be greater than 0")?;
+    }
+    ensure(self.hud.scale > 0, "hud.scale", "must be greater than 0")?;
+
+    for (action, binding) in &self.keys {
+      if !binding.is_empty() {
+        binding.parse::<KeyBinding>().map_err(|source| ConfigError::InvalidKeyBinding {
+          action: action.clone(),
+          source,
+        })?;
+      }
+    }
+
+    Ok(())
+  }
+}
+
+fn valid_obstacle(shape: &ObstacleShape) -> bool {
+  match shape {
+    ObstacleShape::Circle { radius, .. } | ObstacleShape::Capsule { radius, .. } => *radius > 0.0,
+    ObstacleShape::Box { min, max } => min[0] < max[0] && min[1] < max[1],
+    ObstacleShape::Polygon { .. } => true,
+  }
+}
+
+fn ensure(condition: bool, field: &'static str, reason: &'static str) -> Result<(), ConfigError> {
+  match condition {
+    true => Ok(()),
+    false => Err(ConfigError::InvalidValue { field, reason }),
+  }
+}
+
+impl SimConfig {
+  /// Picks a random seed if none is set, so the run can still be reproduced from its manifest
+  pub fn resolve_seed(&mut self) -> u64 {
+    *self.seed.get_or_insert_with(rand::random)
+  }
+}
+
+impl Default for SimConfig {
+  fn default() -> SimConfig {
+    SimConfig {
+      particle_count: 4096,
+      particle_step: 1024,
+      seed: None,
+      triangle_size: 3.5,
+      max_windows: None,
+      max_obstacles: 64,
+      obstacles: Vec::new(),
+      placed_obstacle_radius: 40.0,
+      snapshot_path: PathBuf::from("snapshot.boids"),
+      timestep: TimestepConfig::default(),
+      spawn: SpawnConfig::default(),
+      boundary: BoundaryConfig::default(),
+      interaction: InteractionConfig::default(),
+      rules: BoidRules::default(),
+      species: vec![SpeciesConfig::default()],
+    }
+  }
+}
+
+impl Default for TimestepConfig {
+  fn default() -> TimestepConfig {
+    TimestepConfig {
+      tick_rate: 60.0,
+      max_substeps: 4,
+      max_frame_dt_sec: 0.25,
+      time_scale: 1.0,
+      fixed_dt: false,
+    }
+  }
+}
+
+impl Default for SpawnConfig {
+  fn default() -> SpawnConfig {
+    SpawnConfig {
+      pos_min: [0.0, 0.0],
+      pos_max: [1024.0, 1024.0],
+      max_velocity: 10.0,
+    }
+  }
+}
+
+impl Default for SpeciesConfig {
+  fn default() -> SpeciesConfig {
+    SpeciesConfig {
+      name: String::from("boids"),
+      share: 1.0,
+      color: None,
+      max_speed: None,
+      alignment_radius: None,
+      cohesion_radius: None,
+      separation_radius: None,
+      alignment: Vec::new(),
+      cohesion: Vec::new(),
+      separation: Vec::new(),
+    }
+  }
+}
+
+impl Default for InteractionConfig {
+  fn default() -> InteractionConfig {
+    InteractionConfig {
+      radius: 150.0,
+      strength: 60.0,
+      left_button: InteractionMode::Attract,
+      right_button: InteractionMode::Repel,
+      middle_button: InteractionMode::Vortex,
+    }
+  }
+}
+
+impl Default for WindowConfig {
+  fn default() -> WindowConfig {
+    WindowConfig {
+      title: String::from("learn-wgpu"),
+      size: None,
+      present_mode: PresentMode::AutoVsync,
+      screenshot_dir: PathBuf::from("screenshots"),
+    }
+  }
+}
+
+impl Default for HudConfig {
+  fn default() -> HudConfig {
+    HudConfig { enabled: true, scale: 2 }
+  }
+}
+
+impl Default for GpuConfig {
+  fn default() -> GpuConfig {
+    GpuConfig {
+      power_preference: PowerPreference::default(),
+      backends: None,
+      force_fallback_adapter: false,
+      snapshot_interval_sec: 2.0,
+      profile: true,
+    }
+  }
+}
+
+impl Default for FpsConfig {
+  fn default() -> FpsConfig {
+    FpsConfig {
+      cooldown_sec: 2.0,
+      sample_frames: 240,
+      hitch_factor: 3.0,
+      output: None,
+      output_format: TimeSeriesFormat::Csv,
+      run_for_sec: None,
+    }
+  }
+}
+
+#[allow(clippy::enum_variant_names)]
+#[derive(thiserror::Error, Debug)]
+pub enum ConfigError {
+  #[error("Failed to read config {path}: {source}")]
+  ReadError { path: PathBuf, source: std::io::Error },
+
+  #[error("Failed to parse config: {0}")]
+  ParseError(#[from] toml::de::Error),
+
+  #[error("Invalid value for {field}: {reason}")]
+  InvalidValue { field: &'static str, reason: &'static str },
+
+  #[error("Invalid key binding for {action}: {source}")]
+  InvalidKeyBinding { action: String, source: KeyBindingError },
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn parse(text: &str) -> Result<Config, ConfigError> {
+    Ok(toml::from_str(text)?)
+  }
+
+  /// field the first failed check of `text` reports
+  fn invalid_field(text: &str) -> &'static str {
+    match parse(text).unwrap().validate() {
+      Err(ConfigError::InvalidValue { field, .. }) => field,
+      other => panic!("expected an invalid value, got {other:?}"),
+    }
+  }
+
+  #[test]
+  fn shipped_config_is_valid() {
+    let config = parse(include_str!("../config.toml")).unwrap();
+    config.validate().unwrap();
+  }
+
+  #[test]
+  fn empty_config_is_the_default() {
+    let config = parse("").unwrap();
+    config.validate().unwrap();
+    assert_eq!(config.sim.particle_count, SimConfig::default().particle_count);
+    assert!(config.keys.is_empty());
+  }
+
+  #[test]
+  fn unknown_fields_are_rejected() {
+    for text in [
+      "[sim]\nparticle_cont = 5",
+      "[sim.timestep]\ntick_rte = 60.0",
+      "[windows]\ntitle = \"boids\"",
+      "[hud]\nscal = 2",
+    ] {
+      assert!(matches!(parse(text), Err(ConfigError::ParseError(_))), "{text}");
+    }
+  }
+
+  #[test]
+  fn failed_checks_name_their_field() {
+    let cases = [
+      ("[sim]\nparticle_count = 0", "sim.particle_count"),
+      (
+        "[sim]\nmax_obstacles = 1\nobstacles = [{ shape = \"circle\", center = [0.0, 0.0], radius = 1.0 }, { shape = \"circle\", center = [0.0, 0.0], radius = 1.0 }]",
+        "sim.obstacles",
+      ),
+      (
+        "[[sim.obstacles]]\nshape = \"circle\"\ncenter = [0.0, 0.0]\nradius = 0.0",
+        "sim.obstacles",
+      ),
+      (
+        "[[sim.obstacles]]\nshape = \"polygon\"\npoints = [[0.0, 0.0], [1.0, 0.0]]",
+        "sim.obstacles",
+      ),
+      ("[sim.timestep]\ntick_rate = 0.0", "sim.timestep.tick_rate"),
+      ("[sim.timestep]\ntime_scale = -1.0", "sim.timestep.time_scale"),
+      ("[sim.spawn]\npos_min = [10.0, 0.0]\npos_max = [0.0, 10.0]", "sim.spawn"),
+      ("[sim.boundary]\nworld_min = [0.0, 0.0]", "sim.boundary"),
+      ("[sim.boundary]\nworld_min = [0.0, 0.0]\nworld_max = [0.0, 10.0]", "sim.boundary"),
+      ("[sim.boundary]\nsmoothing = -1.0", "sim.boundary.smoothing"),
+      ("[sim.rules]\nmax_speed = 0.0", "sim.rules.max_speed"),
+      (
+        "[sim.rules]\nxenophobia_start_radius = 20.0\nxenophobia_end_radius = 10.0",
+        "sim.rules.xenophobia_start_radius",
+      ),
+      ("[sim]\nspecies = []", "sim.species"),
+      ("[[sim.species]]\nname = \"a\"\nshare = 0.0", "sim.species.share"),
+      ("[[sim.species]]\nname = \"a\"\ncohesion = [1.0, 1.0]", "sim.species"),
+      ("[window]\nsize = [0, 600]", "window.size"),
+      ("[gpu]\nsnapshot_interval_sec = 0.0", "gpu.snapshot_interval_sec"),
+      ("[fps]\nhitch_factor = 1.0", "fps.hitch_factor"),
+      ("[fps]\nrun_for_sec = 0.0", "fps.run_for_sec"),
+      ("[hud]\nscale = 0", "hud.scale"),
+    ];
+    for (text, field) in cases {
+      assert_eq!(invalid_field(text), field, "{text}");
+    }
+  }
+
+  #[test]
+  fn bad_key_binding_names_its_action() {
+    match parse("[keys]\npause = \"Ctrl+Nope\"").unwrap().validate() {
+      Err(ConfigError::InvalidKeyBinding { action, .. }) => assert_eq!(action, "pause"),
+      other => panic!("expected an invalid key binding, got {other:?}"),
+    }
+    parse("[keys]\npause = \"\"").unwrap().validate().unwrap();
+  }
+}
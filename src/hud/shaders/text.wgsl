@@ -0,0 +1,56 @@
+struct Window {
+  top_left: vec2<f32>,
+  bottom_right: vec2<f32>,
+};
+
+// mirrors `Glyph` in text_pass.rs
+struct GlyphInstance {
+  // top left corner in window pixels
+  @location(0) position: vec2<f32>,
+  @location(1) size: vec2<f32>,
+  // atlas index, SOLID fills the quad with the color
+  @location(2) glyph: u32,
+  @location(3) color: vec4<f32>,
+};
+
+struct VertexOutput {
+  @builtin(position) position: vec4<f32>,
+  // position within the glyph in font pixels
+  @location(0) texel: vec2<f32>,
+  @location(1) @interpolate(flat) glyph: u32,
+  @location(2) color: vec4<f32>,
+};
+
+const GLYPH_SIZE: u32 = 8u;
+const ATLAS_COLUMNS: u32 = 16u;
+const SOLID: u32 = 0xffffffffu;
+
+@group(0) @binding(0) var atlas: texture_2d<f32>;
+@group(1) @binding(0) var<uniform> window: Window;
+
+@vertex
+fn main_vs(@builtin(vertex_index) vertex_index: u32, instance: GlyphInstance) -> VertexOutput {
+  // triangle strip over the corners of the quad
+  let corner = vec2<f32>(f32(vertex_index & 1u), f32(vertex_index >> 1u));
+  let pixel = instance.position + corner * instance.size;
+  let window_size = window.bottom_right - window.top_left;
+
+  var out: VertexOutput;
+  out.position = vec4<f32>(pixel.x / window_size.x * 2.0 - 1.0, 1.0 - pixel.y / window_size.y * 2.0, 0.0, 1.0);
+  out.texel = corner * f32(GLYPH_SIZE);
+  out.glyph = instance.glyph;
+  out.color = instance.color;
+  return out;
+}
+
+@fragment
+fn main_fs(in: VertexOutput) -> @location(0) vec4<f32> {
+  if in.glyph == SOLID {
+    return in.color;
+  }
+
+  let cell = vec2<u32>(in.glyph % ATLAS_COLUMNS, in.glyph / ATLAS_COLUMNS) * GLYPH_SIZE;
+  let texel = cell + min(vec2<u32>(in.texel), vec2<u32>(GLYPH_SIZE - 1u));
+  let coverage = textureLoad(atlas, texel, 0).r;
+  return vec4<f32>(in.color.rgb, in.color.a * coverage);
+}
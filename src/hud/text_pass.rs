@@ -0,0 +1,286 @@
+use crate::{
+  hud::font::{self, GLYPH_SIZE},
+  particle_sim::{render_pass::RenderTarget, view::WindowView},
+};
+use bytemuck::{Pod, Zeroable};
+use std::collections::HashMap;
+use tracing::debug;
+use wgpu::{
+  BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingResource, BindingType,
+  BlendState, Buffer, BufferDescriptor, BufferUsages, ColorTargetState, ColorWrites, CommandEncoder, Device, Extent3d, FragmentState, LoadOp,
+  MultisampleState, Operations, PipelineLayoutDescriptor, PrimitiveState, PrimitiveTopology, Queue, RenderPassColorAttachment, RenderPassDescriptor,
+  RenderPipeline, RenderPipelineDescriptor, ShaderStages, StoreOp, TextureDescriptor, TextureDimension, TextureFormat, TextureSampleType,
+  TextureUsages, TextureView, TextureViewDescriptor, TextureViewDimension, VertexBufferLayout, VertexState, VertexStepMode, include_wgsl,
+  util::{DeviceExt, TextureDataOrder},
+  vertex_attr_array,
+};
+
+/// glyph value that fills the quad with its color instead of sampling the atlas
+const SOLID: u32 = u32::MAX;
+/// glyphs a new `HudText` has room for
+const INITIAL_CAPACITY: usize = 256;
+
+const TEXT_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+const PANEL_COLOR: [f32; 4] = [0.0, 0.0, 0.0, 0.6];
+/// distance of the panel from the window corner, in window pixels
+const MARGIN: f32 = 8.0;
+/// space between the panel edge and the text and between lines, in font pixels
+const PADDING: u32 = 3;
+const LINE_SPACING: u32 = 2;
+
+/// One quad of the HUD, mirrors `GlyphInstance` in text.wgsl
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct Glyph {
+  position: [f32; 2],
+  size: [f32; 2],
+  glyph: u32,
+  color: [f32; 4],
+}
+
+/// Lines of text shown over a single window, laid out as one quad per glyph.
+///
+/// Every window owns one like its `WindowView`, so several windows can be drawn in the same submit
+pub struct HudText {
+  buffer: Buffer,
+  capacity: usize,
+  glyph_count: u32,
+}
+
+impl HudText {
+  pub fn new(device: &Device) -> HudText {
+    HudText {
+      buffer: HudText::init_buffer(device, INITIAL_CAPACITY),
+      capacity: INITIAL_CAPACITY,
+      glyph_count: 0,
+    }
+  }
+
+  /// Shows `lines` on a panel in the top left corner with every font pixel `scale` window pixels large,
+  /// no lines hide the HUD. The glyph buffer grows if the text doesn't fit
+  pub fn update(&mut self, device: &Device, queue: &Queue, lines: &[String], scale: u32) {
+    let glyphs = HudText::layout(lines, scale as f32);
+    if glyphs.len() > self.capacity {
+      self.capacity = glyphs.len().next_power_of_two();
+      self.buffer = HudText::init_buffer(device, self.capacity);
+    }
+
+    queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&glyphs));
+    self.glyph_count = glyphs.len() as u32;
+  }
+
+  /// Panel quad followed by a quad for every visible character
+  fn layout(lines: &[String], scale: f32) -> Vec<Glyph> {
+    let columns = lines.iter().map(|line| line.chars().count()).max().unwrap_or(0);
+    if columns == 0 {
+      return Vec::new();
+    }
+
+    let glyph_size = GLYPH_SIZE as f32 * scale;
+    let line_height = (GLYPH_SIZE + LINE_SPACING) as f32 * scale;
+    let padding = PADDING as f32 * scale;
+
+    let mut glyphs = vec![Glyph {
+      position: [MARGIN, MARGIN],
+      size: [
+        columns as f32 * glyph_size + 2.0 * padding,
+        lines.len() as f32 * line_height - LINE_SPACING as f32 * scale + 2.0 * padding,
+      ],
+      glyph: SOLID,
+      color: PANEL_COLOR,
+    }];
+
+    for (row, line) in lines.iter().enumerate() {
+      let top = MARGIN + padding + row as f32 * line_height;
+      for (column, c) in line.chars().enumerate() {
+        if c == ' ' {
+          continue;
+        }
+
+        glyphs.push(Glyph {
+          position: [MARGIN + padding + column as f32 * glyph_size, top],
+          size: [glyph_size, glyph_size],
+          glyph: font::glyph_index(c),
+          color: TEXT_COLOR,
+        });
+      }
+    }
+
+    glyphs
+  }
+
+  fn init_buffer(device: &Device, capacity: usize) -> Buffer {
+    device.create_buffer(&BufferDescriptor {
+      label: Some("HUD glyph buffer"),
+      size: (capacity * size_of::<Glyph>()) as u64,
+      usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
+      mapped_at_creation: false,
+    })
+  }
+}
+
+/// Draws `HudText` over a window with the built-in bitmap font
+pub struct TextPass {
+  atlas_layout: BindGroupLayout,
+  atlas_bind_group: BindGroup,
+  view_layout: BindGroupLayout,
+  pipelines: HashMap<RenderTarget, RenderPipeline>,
+}
+
+impl TextPass {
+  /// Draws `text` on top of whatever `view` already shows, `view` has to match `target`.
+  /// Pipelines for a new target are built on first use
+  pub fn run(
+    &mut self,
+    encoder: &mut CommandEncoder,
+    device: &Device,
+    window_view: &WindowView,
+    text: &HudText,
+    view: &TextureView,
+    target: RenderTarget,
+  ) {
+    if text.glyph_count == 0 {
+      return;
+    }
+
+    self.prepare(device, target);
+
+    let color_attachments = [Some(RenderPassColorAttachment {
+      view,
+      depth_slice: None,
+      resolve_target: None,
+      ops: Operations {
+        load: LoadOp::Load,
+        store: StoreOp::Store,
+      },
+    })];
+
+    let mut rpass = encoder.begin_render_pass(&RenderPassDescriptor {
+      label: Some("HUD render pass"),
+      color_attachments: &color_attachments,
+      depth_stencil_attachment: None,
+      timestamp_writes: None,
+      occlusion_query_set: None,
+      multiview_mask: None,
+    });
+
+    rpass.set_pipeline(&self.pipelines[&target]);
+    rpass.set_bind_group(0, &self.atlas_bind_group, &[]);
+    rpass.set_bind_group(1, window_view.bind_group(), &[]);
+    rpass.set_vertex_buffer(0, text.buffer.slice(..));
+    rpass.draw(0..4, 0..text.glyph_count);
+  }
+
+  pub fn init(device: &Device, queue: &Queue) -> TextPass {
+    let atlas_layout = TextPass::init_atlas_bind_group_layout(device);
+    let atlas_view = TextPass::init_atlas(device, queue);
+    let atlas_bind_group = device.create_bind_group(&BindGroupDescriptor {
+      label: Some("Font atlas bind group"),
+      layout: &atlas_layout,
+      entries: &[BindGroupEntry {
+        binding: 0,
+        resource: BindingResource::TextureView(&atlas_view),
+      }],
+    });
+
+    TextPass {
+      atlas_layout,
+      atlas_bind_group,
+      view_layout: WindowView::init_layout(device),
+      pipelines: HashMap::new(),
+    }
+  }
+
+  fn prepare(&mut self, device: &Device, target: RenderTarget) {
+    self.pipelines.entry(target).or_insert_with(|| {
+      debug!("Building HUD pipeline for {target:?}");
+      TextPass::init_pipeline(device, &[&self.atlas_layout, &self.view_layout], target)
+    });
+  }
+
+  fn init_atlas(device: &Device, queue: &Queue) -> TextureView {
+    let ([width, height], pixels) = font::atlas();
+    let texture = device.create_texture_with_data(
+      queue,
+      &TextureDescriptor {
+        label: Some("Font atlas"),
+        size: Extent3d {
+          width,
+          height,
+          depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: TextureDimension::D2,
+        format: TextureFormat::R8Unorm,
+        usage: TextureUsages::TEXTURE_BINDING,
+        view_formats: &[],
+      },
+      TextureDataOrder::LayerMajor,
+      &pixels,
+    );
+
+    texture.create_view(&TextureViewDescriptor::default())
+  }
+
+  fn init_atlas_bind_group_layout(device: &Device) -> BindGroupLayout {
+    device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+      label: Some("Font atlas bind group layout"),
+      entries: &[BindGroupLayoutEntry {
+        binding: 0,
+        visibility: ShaderStages::FRAGMENT,
+        ty: BindingType::Texture {
+          sample_type: TextureSampleType::Float { filterable: false },
+          view_dimension: TextureViewDimension::D2,
+          multisampled: false,
+        },
+        count: None,
+      }],
+    })
+  }
+
+  fn init_pipeline(device: &Device, bind_group_layouts: &[&BindGroupLayout], target: RenderTarget) -> RenderPipeline {
+    let shader = device.create_shader_module(include_wgsl!("shaders/text.wgsl"));
+    let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+      label: Some("HUD pipeline layout"),
+      bind_group_layouts,
+      immediate_size: 0,
+    });
+
+    device.create_render_pipeline(&RenderPipelineDescriptor {
+      label: Some("HUD pipeline"),
+      layout: Some(&pipeline_layout),
+      vertex: VertexState {
+        module: &shader,
+        entry_point: Some("main_vs"),
+        compilation_options: Default::default(),
+        buffers: &[VertexBufferLayout {
+          array_stride: size_of::<Glyph>() as u64,
+          step_mode: VertexStepMode::Instance,
+          attributes: &vertex_attr_array![0 => Float32x2, 1 => Float32x2, 2 => Uint32, 3 => Float32x4],
+        }],
+      },
+      fragment: Some(FragmentState {
+        module: &shader,
+        entry_point: Some("main_fs"),
+        compilation_options: Default::default(),
+        targets: &[Some(ColorTargetState {
+          format: target.format,
+          blend: Some(BlendState::ALPHA_BLENDING),
+          write_mask: ColorWrites::ALL,
+        })],
+      }),
+      primitive: PrimitiveState {
+        topology: PrimitiveTopology::TriangleStrip,
+        ..Default::default()
+      },
+      depth_stencil: None,
+      multisample: MultisampleState {
+        count: target.sample_count,
+        ..Default::default()
+      },
+      multiview_mask: None,
+      cache: None,
+    })
+  }
+}
@@ -1,36 +1,118 @@
-use tracing::Level;
+use clap::Parser;
+use std::process::ExitCode;
+use tracing::{error, info, warn};
 use tracing_log::LogTracer;
-use tracing_subscriber::FmtSubscriber;
+use tracing_subscriber::{EnvFilter, FmtSubscriber};
 use winit::event_loop::{ControlFlow, EventLoop};
 
 mod app;
+mod cli;
+mod config;
+mod hud;
+mod manifest;
 mod modules;
 mod particle_sim;
 
-use app::app::App;
+use app::{app::App, headless::HeadlessApp};
 
-use crate::modules::fps::FpsModule;
+use crate::{
+  cli::{Cli, Platform},
+  config::Config,
+  manifest::{RunManifest, RunRecorder},
+  modules::fps::FpsModule,
+};
 
 #[tokio::main]
-async fn main() {
+async fn main() -> ExitCode {
+  let cli = Cli::parse();
+
   LogTracer::init().expect("failed to set logger");
-  let subscriber = FmtSubscriber::builder().with_max_level(Level::INFO).finish();
+  let filter = match &cli.log_level {
+    Some(level) => EnvFilter::try_new(level),
+    None => EnvFilter::try_from_default_env().or_else(|_| EnvFilter::try_new("info")),
+  };
+  // --log-level is checked by clap, only the "info" fallback is left
+  let filter = filter.expect("Invalid log level filter");
+  let subscriber = FmtSubscriber::builder().with_env_filter(filter).finish();
 
   tracing::subscriber::set_global_default(subscriber).expect("Failed to set global subscriber");
 
+  let replay = match cli.replay.as_deref().map(RunManifest::load).transpose() {
+    Ok(replay) => replay,
+    Err(e) => {
+      error!("{e}");
+      return ExitCode::FAILURE;
+    }
+  };
+
+  let config = match &replay {
+    Some(manifest) => Ok(manifest.config.clone()),
+    None => Config::load_or_default(&cli.config),
+  };
+  let config = config.and_then(|mut config| {
+    cli.apply(&mut config);
+    config.validate()?;
+    Ok(config)
+  });
+  let mut config = match config {
+    Ok(config) => config,
+    Err(e) => {
+      error!("{e}");
+      return ExitCode::FAILURE;
+    }
+  };
+  info!("Seed: {}", config.sim.resolve_seed());
+
+  let recorder = RunRecorder::new(cli.manifest.clone(), replay);
+
+  if let Some(frames) = cli.headless {
+    let replay_steps = recorder.replay.as_ref().map(|manifest| manifest.steps.min(u32::MAX as u64) as u32);
+    let frames = match (frames, replay_steps) {
+      (Some(frames), Some(replay_steps)) if frames != replay_steps => {
+        warn!("Running {frames} steps, the replayed run recorded {replay_steps}");
+        frames
+      }
+      (frames, replay_steps) => frames.or(replay_steps).unwrap_or(1),
+    };
+
+    let mut headless = HeadlessApp {
+      frames,
+      output_dir: cli.output.clone(),
+      save_every_frame: cli.save_every_frame,
+      recorder,
+      load_snapshot: cli.load_snapshot.clone(),
+      save_snapshot: cli.save_snapshot.clone(),
+      ..HeadlessApp::new(config)
+    };
+
+    if let Err(e) = headless.run().await {
+      error!("Headless run failed: {e}");
+      return ExitCode::FAILURE;
+    }
+    return ExitCode::SUCCESS;
+  }
+
   let mut builder = EventLoop::builder();
 
   #[cfg(target_os = "linux")]
-  {
-    use winit::platform::x11::EventLoopBuilderExtX11;
-    builder.with_x11();
+  match cli.platform {
+    Platform::Auto => {}
+    Platform::X11 => {
+      use winit::platform::x11::EventLoopBuilderExtX11;
+      builder.with_x11();
+    }
+    Platform::Wayland => {
+      use winit::platform::wayland::EventLoopBuilderExtWayland;
+      builder.with_wayland();
+    }
   }
 
   let event_loop = builder.build().unwrap();
   event_loop.set_control_flow(ControlFlow::Poll);
 
-  let mut app = App::default();
-  app.add_module(Box::new(FpsModule::default()));
+  let mut app = App::new(config.clone(), recorder, cli.load_snapshot.clone());
+  app.add_module(Box::new(FpsModule::new(&config.fps)));
 
   event_loop.run_app(&mut app).unwrap();
+  ExitCode::SUCCESS
 }
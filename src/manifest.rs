@@ -0,0 +1,123 @@
+use crate::config::Config;
+use serde::{Deserialize, Serialize};
+use std::{
+  fs,
+  path::{Path, PathBuf},
+};
+use tracing::{info, warn};
+use wgpu::AdapterInfo;
+
+/// Record of a run: the exact config with its resolved seed and the adapter it ran on.
+/// Loading it with `--replay` runs the recorded number of steps, which on the same adapter reaches
+/// the same particle state; windowed runs additionally depend on where the windows were
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RunManifest {
+  pub version: String,
+  pub seed: u64,
+  /// simulation steps run, filled in when the run ends
+  pub steps: u64,
+  pub adapter: AdapterInfo,
+  pub config: Config,
+}
+
+impl RunManifest {
+  pub fn new(config: &Config, adapter: AdapterInfo) -> RunManifest {
+    RunManifest {
+      version: env!("CARGO_PKG_VERSION").to_string(),
+      seed: config.sim.seed.unwrap_or_default(),
+      steps: 0,
+      adapter,
+      config: config.clone(),
+    }
+  }
+
+  pub fn load(path: &Path) -> Result<RunManifest, ManifestError> {
+    let text = fs::read_to_string(path).map_err(|source| ManifestError::ReadError {
+      path: path.to_path_buf(),
+      source,
+    })?;
+
+    Ok(toml::from_str(&text)?)
+  }
+
+  pub fn save(&self, path: &Path) -> Result<(), ManifestError> {
+    let text = toml::to_string_pretty(self)?;
+    fs::write(path, text).map_err(|source| ManifestError::WriteError {
+      path: path.to_path_buf(),
+      source,
+    })
+  }
+}
+
+/// Writes the manifest of the current run and checks it against the run being replayed
+#[derive(Default)]
+pub struct RunRecorder {
+  /// where the manifest of this run is written, nothing is written when not set
+  pub path: Option<PathBuf>,
+  /// manifest of the run being replayed
+  pub replay: Option<RunManifest>,
+  manifest: Option<RunManifest>,
+}
+
+impl RunRecorder {
+  pub fn new(path: Option<PathBuf>, replay: Option<RunManifest>) -> RunRecorder {
+    RunRecorder {
+      path,
+      replay,
+      manifest: None,
+    }
+  }
+
+  /// Call once the adapter is known
+  pub fn start(&mut self, config: &Config, adapter: AdapterInfo) {
+    if let Some(replay) = &self.replay {
+      if replay.adapter != adapter {
+        warn!(
+          "Replaying a run from {} ({:?}) on {} ({:?}), results may differ",
+          replay.adapter.name, replay.adapter.backend, adapter.name, adapter.backend
+        );
+      }
+      info!("Replaying {} steps to reach the recorded state", replay.steps);
+    }
+
+    let manifest = RunManifest::new(config, adapter);
+    self.save(&manifest);
+    self.manifest = Some(manifest);
+  }
+
+  /// Call when the run ends, records how many steps were run
+  pub fn finish(&mut self, steps: u64) {
+    if let Some(mut manifest) = self.manifest.take() {
+      manifest.steps = steps;
+      self.save(&manifest);
+    }
+  }
+
+  fn save(&self, manifest: &RunManifest) {
+    let Some(path) = &self.path else {
+      return;
+    };
+
+    match manifest.save(path) {
+      Ok(()) => info!("Run manifest written to {}", path.display()),
+      Err(e) => warn!("Failed to write run manifest: {e}"),
+    }
+  }
+}
+
+#[allow(clippy::enum_variant_names)]
+#[derive(thiserror::Error, Debug)]
+pub enum ManifestError {
+  #[error("Failed to read manifest {path}: {source}")]
+  ReadError { path: PathBuf, source: std::io::Error },
+
+  #[error("Failed to write manifest {path}: {source}")]
+  WriteError { path: PathBuf, source: std::io::Error },
+
+  #[error("Failed to parse manifest: {0}")]
+  ParseError(#[from] toml::de::Error),
+
+  #[error("Failed to serialize manifest: {0}")]
+  SerializeError(#[from] toml::ser::Error),
+}
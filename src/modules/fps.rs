@@ -1,51 +1,446 @@
-use std::time::Instant;
+use std::{
+  collections::{HashMap, VecDeque},
+  fs::File,
+  io::{self, BufWriter, Write},
+  path::{Path, PathBuf},
+  time::Duration,
+};
 
-use tracing::info;
+use serde::{Serialize, Serializer};
+use tracing::{error, info, warn};
 use winit::window::WindowId;
 
-use crate::app::module::Module;
+use crate::{
+  app::{
+    action::Action,
+    gpu_profiler::GpuProfiler,
+    module::{Module, ModuleContext},
+  },
+  config::{FpsConfig, TimeSeriesFormat},
+};
 
+/// frames a window has to have rendered before hitches are reported, the first frames are always slow
+const HITCH_WARMUP_FRAMES: usize = 10;
+
+/// Logs frame time stats of every window and the compute tick rate every `cooldown_sec`,
+/// and exits the app after `run_for_sec` if set
 pub struct FpsModule {
-  window_id: Option<WindowId>,
-  last_print: Option<Instant>,
-  frame_count: u64,
+  windows: HashMap<WindowId, WindowFrames>,
+  /// compute steps since the last report
+  steps: u64,
+  last_report: Option<Duration>,
+  time_series: Option<TimeSeries>,
 
   cooldown_sec: f64,
+  sample_frames: usize,
+  hitch_factor: f64,
+  output: Option<PathBuf>,
+  output_format: TimeSeriesFormat,
+  /// cleared once the exit is requested
+  run_for_sec: Option<f64>,
 }
 
 impl Module for FpsModule {
-  fn on_render(&mut self, window_id: WindowId) {
-    match self.window_id {
-      Some(my_id) if my_id != window_id => return,
-      None => self.window_id = Some(window_id),
-      _ => {}
-    }
+  fn on_init(&mut self, ctx: &mut ModuleContext) {
+    info!("Measuring FPS on {}", ctx.gpu.adapter.get_info().name);
 
-    let now = Instant::now();
-    self.frame_count += 1;
+    if let Some(path) = &self.output {
+      match TimeSeries::create(path, self.output_format) {
+        Ok(time_series) => {
+          info!("Writing frame stats to {}", path.display());
+          self.time_series = Some(time_series);
+        }
+        Err(e) => error!("Failed to create {}, frame stats are only logged: {e}", path.display()),
+      }
+    }
+  }
 
-    match self.last_print {
+  fn on_update(&mut self, ctx: &mut ModuleContext, _dt: Duration) {
+    let now = ctx.elapsed;
+    match self.last_report {
       Some(last) => {
         let elapsed = (now - last).as_secs_f64();
         if elapsed >= self.cooldown_sec {
-          let fps = self.frame_count as f64 / elapsed;
-          info!("{window_id:?} FPS: {:.1}", fps);
-          self.frame_count = 0;
-          self.last_print = Some(now);
+          self.report(ctx, elapsed);
+          self.last_report = Some(now);
         }
       }
-      None => self.last_print = Some(now),
+      None => self.last_report = Some(now),
+    }
+
+    if let Some(run_for_sec) = self.run_for_sec
+      && now.as_secs_f64() >= run_for_sec
+    {
+      // stats since the last report would be lost otherwise
+      if let Some(last) = self.last_report
+        && now > last
+      {
+        self.report(ctx, (now - last).as_secs_f64());
+      }
+      info!("Ran for {run_for_sec}s, exiting");
+      ctx.request(Action::Exit);
+      self.run_for_sec = None;
+    }
+  }
+
+  fn on_compute(&mut self, _ctx: &mut ModuleContext, steps: u32) {
+    self.steps += steps as u64;
+  }
+
+  fn on_render(&mut self, ctx: &mut ModuleContext) {
+    let Some(window) = ctx.window else {
+      return;
+    };
+    let window_id = window.window.id();
+
+    let now = ctx.elapsed;
+    let frames = self.windows.entry(window_id).or_default();
+    frames.frames += 1;
+    let Some(last_frame) = frames.last_frame.replace(now) else {
+      return;
+    };
+
+    let frame_ms = (now - last_frame).as_secs_f64() * 1000.0;
+    if let Some(average_ms) = frames.hitch_average(frame_ms, self.hitch_factor) {
+      warn!(
+        "{window_id:?} hitch: {frame_ms:.1}ms frame, {:.1}x the {average_ms:.2}ms average",
+        frame_ms / average_ms
+      );
+      frames.hitches += 1;
+    }
+
+    frames.frame_times.push_back(frame_ms);
+    if frames.frame_times.len() > self.sample_frames {
+      frames.frame_times.pop_front();
+    }
+  }
+
+  fn on_hud(&mut self, ctx: &mut ModuleContext, lines: &mut Vec<String>) {
+    let Some(window) = ctx.window else {
+      return;
+    };
+    let window_id = window.window.id();
+    let Some(stats) = self.windows.get(&window_id).and_then(|frames| FrameStats::new(&frames.frame_times)) else {
+      return;
+    };
+
+    lines.push(format!(
+      "FPS: {:.1}, frame avg/p99: {:.2}/{:.2}ms",
+      1000.0 / stats.avg,
+      stats.avg,
+      stats.p99
+    ));
+    if let (Some(compute_ms), Some(render_ms)) = (ctx.gpu_timings.get("compute"), ctx.gpu_timings.get(&GpuProfiler::render_label(window_id))) {
+      lines.push(format!("GPU compute: {compute_ms:.2}ms, render: {render_ms:.2}ms"));
+    }
+  }
+
+  fn on_window_close(&mut self, ctx: &mut ModuleContext) {
+    if let Some(window) = ctx.window {
+      self.windows.remove(&window.window.id());
+    }
+  }
+
+  fn on_shutdown(&mut self, _ctx: &mut ModuleContext) {
+    if let Some(time_series) = self.time_series.as_mut()
+      && let Err(e) = time_series.writer.flush()
+    {
+      error!("Failed to write frame stats: {e}");
     }
   }
 }
 
-impl Default for FpsModule {
-  fn default() -> FpsModule {
+impl FpsModule {
+  pub fn new(config: &FpsConfig) -> FpsModule {
     FpsModule {
-      window_id: None,
-      last_print: None,
-      frame_count: 0,
-      cooldown_sec: 2.0,
+      windows: HashMap::new(),
+      steps: 0,
+      last_report: None,
+      time_series: None,
+      cooldown_sec: config.cooldown_sec,
+      sample_frames: config.sample_frames,
+      hitch_factor: config.hitch_factor,
+      output: config.output.clone(),
+      output_format: config.output_format,
+      run_for_sec: config.run_for_sec,
+    }
+  }
+
+  /// Logs and writes out the stats gathered over the last `elapsed` seconds, then starts counting anew
+  fn report(&mut self, ctx: &ModuleContext, elapsed: f64) {
+    let tick_rate = self.steps as f64 / elapsed;
+    self.steps = 0;
+    info!("Compute: {tick_rate:.1} ticks/s");
+
+    let mut window_ids: Vec<WindowId> = self.windows.keys().copied().collect();
+    window_ids.sort_unstable();
+
+    for window_id in window_ids {
+      let frames = self.windows.get_mut(&window_id).unwrap();
+      let Some(stats) = FrameStats::new(&frames.frame_times) else {
+        continue;
+      };
+
+      let report = Report {
+        time_sec: ctx.elapsed.as_secs_f64(),
+        window_id,
+        fps: frames.frames as f64 / elapsed,
+        stats,
+        hitches: frames.hitches,
+        tick_rate,
+        // GPU times are only there if the device supports timestamp queries
+        gpu_compute_ms: ctx.gpu_timings.get("compute").copied(),
+        gpu_render_ms: ctx.gpu_timings.get(&GpuProfiler::render_label(window_id)).copied(),
+      };
+      frames.frames = 0;
+      frames.hitches = 0;
+
+      report.log();
+      if let Some(time_series) = self.time_series.as_mut()
+        && let Err(e) = time_series.write(&report)
+      {
+        error!("Failed to write frame stats, only logging them from now on: {e}");
+        self.time_series = None;
+      }
+    }
+
+    if let Some(time_series) = self.time_series.as_mut()
+      && let Err(e) = time_series.writer.flush()
+    {
+      error!("Failed to write frame stats, only logging them from now on: {e}");
+      self.time_series = None;
+    }
+  }
+}
+
+#[derive(Default)]
+struct WindowFrames {
+  last_frame: Option<Duration>,
+  /// durations of the last `sample_frames` frames, in milliseconds
+  frame_times: VecDeque<f64>,
+  /// frames and hitches since the last report
+  frames: u64,
+  hitches: u64,
+}
+
+impl WindowFrames {
+  /// The average frame time if `frame_ms` took over `hitch_factor` times as long, `None` for regular frames and during warmup
+  fn hitch_average(&self, frame_ms: f64, hitch_factor: f64) -> Option<f64> {
+    if self.frame_times.len() < HITCH_WARMUP_FRAMES {
+      return None;
+    }
+
+    let average_ms = self.frame_times.iter().sum::<f64>() / self.frame_times.len() as f64;
+    (frame_ms > average_ms * hitch_factor).then_some(average_ms)
+  }
+}
+
+/// Frame times over the sample window, in milliseconds
+#[derive(Debug, PartialEq, Serialize)]
+struct FrameStats {
+  #[serde(rename = "frame_min_ms")]
+  min: f64,
+  #[serde(rename = "frame_avg_ms")]
+  avg: f64,
+  #[serde(rename = "frame_p95_ms")]
+  p95: f64,
+  #[serde(rename = "frame_p99_ms")]
+  p99: f64,
+}
+
+impl FrameStats {
+  /// `None` until at least one frame time was recorded
+  fn new(frame_times: &VecDeque<f64>) -> Option<FrameStats> {
+    if frame_times.is_empty() {
+      return None;
+    }
+
+    let mut sorted: Vec<f64> = frame_times.iter().copied().collect();
+    sorted.sort_unstable_by(f64::total_cmp);
+
+    // nearest rank percentile
+    let percentile = |p: f64| sorted[((p * sorted.len() as f64).ceil() as usize).clamp(1, sorted.len()) - 1];
+
+    Some(FrameStats {
+      min: sorted[0],
+      avg: sorted.iter().sum::<f64>() / sorted.len() as f64,
+      p95: percentile(0.95),
+      p99: percentile(0.99),
+    })
+  }
+}
+
+/// Stats of one window over one reporting interval, serialized as a JSONL row
+#[derive(Serialize)]
+struct Report {
+  /// seconds since the app started
+  time_sec: f64,
+  #[serde(rename = "window", serialize_with = "serialize_window_id")]
+  window_id: WindowId,
+  fps: f64,
+  #[serde(flatten)]
+  stats: FrameStats,
+  hitches: u64,
+  /// compute steps per second, shared by every window
+  #[serde(rename = "ticks_per_sec")]
+  tick_rate: f64,
+  gpu_compute_ms: Option<f64>,
+  gpu_render_ms: Option<f64>,
+}
+
+fn serialize_window_id<S: Serializer>(window_id: &WindowId, serializer: S) -> Result<S::Ok, S::Error> {
+  serializer.serialize_u64(u64::from(*window_id))
+}
+
+impl Report {
+  fn log(&self) {
+    let Report {
+      window_id,
+      fps,
+      stats,
+      hitches,
+      ..
+    } = self;
+    let FrameStats { min, avg, p95, p99 } = stats;
+    let frame_times = format!("frame min/avg/p95/p99: {min:.2}/{avg:.2}/{p95:.2}/{p99:.2}ms");
+
+    match (self.gpu_compute_ms, self.gpu_render_ms) {
+      (Some(compute_ms), Some(render_ms)) => {
+        info!("{window_id:?} FPS: {fps:.1}, {frame_times}, hitches: {hitches}, GPU compute: {compute_ms:.3}ms, GPU render: {render_ms:.3}ms")
+      }
+      _ => info!("{window_id:?} FPS: {fps:.1}, {frame_times}, hitches: {hitches}"),
+    }
+  }
+}
+
+/// Log of every report, for comparing runs across changes
+struct TimeSeries {
+  writer: BufWriter<File>,
+  format: TimeSeriesFormat,
+}
+
+impl TimeSeries {
+  const CSV_HEADER: &str =
+    "time_sec,window,fps,frame_min_ms,frame_avg_ms,frame_p95_ms,frame_p99_ms,hitches,ticks_per_sec,gpu_compute_ms,gpu_render_ms";
+
+  /// Creates or truncates `path`, csv files start with a header row
+  fn create(path: &Path, format: TimeSeriesFormat) -> io::Result<TimeSeries> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    if format == TimeSeriesFormat::Csv {
+      writeln!(writer, "{}", TimeSeries::CSV_HEADER)?;
+    }
+
+    Ok(TimeSeries { writer, format })
+  }
+
+  fn write(&mut self, report: &Report) -> io::Result<()> {
+    match self.format {
+      TimeSeriesFormat::Csv => {
+        let Report {
+          time_sec,
+          window_id,
+          fps,
+          stats,
+          hitches,
+          tick_rate,
+          gpu_compute_ms,
+          gpu_render_ms,
+        } = report;
+        let FrameStats { min, avg, p95, p99 } = stats;
+        let window = u64::from(*window_id);
+        // missing GPU times are left empty
+        let optional = |value: &Option<f64>| value.map(|value| format!("{value:.4}")).unwrap_or_default();
+        writeln!(
+          self.writer,
+          "{time_sec:.3},{window},{fps:.2},{min:.4},{avg:.4},{p95:.4},{p99:.4},{hitches},{tick_rate:.2},{},{}",
+          optional(gpu_compute_ms),
+          optional(gpu_render_ms),
+        )
+      }
+      // non-finite values, e.g. the fps of a zero length interval, are written as null
+      TimeSeriesFormat::Jsonl => {
+        serde_json::to_writer(&mut self.writer, report)?;
+        writeln!(self.writer)
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn frames(frame_times: &[f64]) -> WindowFrames {
+    WindowFrames {
+      frame_times: frame_times.iter().copied().collect(),
+      ..WindowFrames::default()
     }
   }
+
+  #[test]
+  fn frame_stats_use_nearest_rank_percentiles() {
+    // shuffled 1..=100
+    let frame_times: VecDeque<f64> = (0..100).map(|i| ((i * 37) % 100 + 1) as f64).collect();
+
+    let stats = FrameStats::new(&frame_times).unwrap();
+    assert_eq!(
+      stats,
+      FrameStats {
+        min: 1.0,
+        avg: 50.5,
+        p95: 95.0,
+        p99: 99.0,
+      }
+    );
+  }
+
+  #[test]
+  fn frame_stats_of_few_frames() {
+    assert_eq!(FrameStats::new(&VecDeque::new()), None);
+
+    let stats = FrameStats::new(&VecDeque::from([4.0])).unwrap();
+    assert_eq!((stats.min, stats.avg, stats.p95, stats.p99), (4.0, 4.0, 4.0, 4.0));
+
+    // a single slow frame among ten is above the 95th percentile
+    let stats = FrameStats::new(&[2.0; 9].into_iter().chain([20.0]).collect()).unwrap();
+    assert_eq!((stats.p95, stats.p99), (20.0, 20.0));
+  }
+
+  #[test]
+  fn hitches_are_slow_frames_after_warmup() {
+    let warm = frames(&[10.0; HITCH_WARMUP_FRAMES]);
+    assert_eq!(warm.hitch_average(31.0, 3.0), Some(10.0));
+    assert_eq!(warm.hitch_average(30.0, 3.0), None);
+
+    let warming_up = frames(&[10.0; HITCH_WARMUP_FRAMES - 1]);
+    assert_eq!(warming_up.hitch_average(100.0, 3.0), None);
+  }
+
+  #[test]
+  fn jsonl_row_stays_valid_with_non_finite_values() {
+    let report = Report {
+      time_sec: 2.0,
+      window_id: WindowId::from(7),
+      fps: f64::INFINITY,
+      stats: FrameStats {
+        min: 1.0,
+        avg: f64::NAN,
+        p95: 3.0,
+        p99: 4.0,
+      },
+      hitches: 1,
+      tick_rate: 60.0,
+      gpu_compute_ms: None,
+      gpu_render_ms: Some(0.5),
+    };
+
+    let row: serde_json::Value = serde_json::from_str(&serde_json::to_string(&report).unwrap()).unwrap();
+    assert_eq!(row["window"], 7);
+    assert_eq!(row["fps"], serde_json::Value::Null);
+    assert_eq!(row["frame_avg_ms"], serde_json::Value::Null);
+    assert_eq!(row["frame_p99_ms"], 4.0);
+    assert_eq!(row["ticks_per_sec"], 60.0);
+    assert_eq!(row["gpu_compute_ms"], serde_json::Value::Null);
+    assert_eq!(row["gpu_render_ms"], 0.5);
+  }
 }
@@ -1,89 +1,347 @@
 use crate::{
   app::gpu_wrapper::GpuWrapper,
-  particle_sim::{params::Params, particle::Particle},
+  config::{BoundaryConfig, SimConfig, SpawnConfig, SpeciesConfig},
+  particle_sim::{
+    params::{BoidRules, Interaction, Params},
+    particle::Particle,
+    readback::{Readback, ReadbackError},
+    species::SpeciesTable,
+    window::Window,
+  },
 };
-use std::time::Instant;
+use rand::{SeedableRng, rngs::StdRng};
 use wgpu::{
   BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingType, Buffer,
-  BufferBindingType, BufferSize, BufferUsages, CommandEncoder, ComputePassDescriptor, ComputePipeline, ComputePipelineDescriptor, Device,
-  PipelineLayoutDescriptor, Queue, ShaderStages, include_wgsl,
+  BufferBindingType, BufferDescriptor, BufferSize, BufferUsages, CommandEncoder, CommandEncoderDescriptor, ComputePass as WgpuComputePass,
+  ComputePassDescriptor, ComputePassTimestampWrites, ComputePipeline, ComputePipelineDescriptor, Device, PipelineLayoutDescriptor, Queue,
+  ShaderModule, ShaderModuleDescriptor, ShaderSource, ShaderStages,
   util::{BufferInitDescriptor, DeviceExt},
 };
 
+const WORKGROUP_SIZE: u32 = 64;
+/// particle buffers are allocated this much larger than needed, so small additions don't reallocate
+const CAPACITY_HEADROOM: f32 = 1.5;
+
 pub struct ComputePass {
+  rules: BoidRules,
+  interaction: Interaction,
   params_buffer: Buffer,
+  window_buffer: Buffer,
+  layout: BindGroupLayout,
   pipeline: ComputePipeline,
+  storage: ParticleStorage,
+  species: SpeciesBuffers,
+  obstacle_bind_group: BindGroup,
+  obstacle_count: u32,
+  boundary: BoundaryConfig,
+  step_index: u32,
 
-  bind_group_a: BindGroup,
-  bind_group_b: BindGroup,
+  particle_count: u32,
+  write_to_buffer_a: bool,
+
+  spawn: SpawnConfig,
+  rng: StdRng,
+  /// particles drawn from `rng` since it was seeded, lets a rebuilt simulation continue the spawn sequence
+  spawned: u64,
+}
 
+/// Species table and weight matrix, bound as group 1 of the move pass
+struct SpeciesBuffers {
+  configs: Vec<SpeciesConfig>,
+  table: SpeciesTable,
+  species_buffer: Buffer,
+  weights_buffer: Buffer,
+  layout: BindGroupLayout,
+  bind_group: BindGroup,
+}
+
+/// Everything sized by the particle capacity, recreated together when the population outgrows it
+struct ParticleStorage {
+  capacity: u32,
   particle_buffer_a: Buffer,
   particle_buffer_b: Buffer,
-  particle_count: u32,
-
-  write_to_buffer_a: bool,
-  last_run: Option<Instant>,
+  grid: SpatialGrid,
+  bind_group_a: BindGroup,
+  bind_group_b: BindGroup,
 }
 
 impl ComputePass {
-  pub fn init(gpu: &GpuWrapper, window_buffer: &Buffer) -> ComputePass {
+  pub fn init(gpu: &GpuWrapper, window_buffer: &Buffer, obstacle_buffer: &Buffer, config: &SimConfig) -> ComputePass {
+    let mut rng = ComputePass::init_rng(config);
+    let particles = (0..config.particle_count)
+      .map(|_| Particle::random(&config.spawn, &config.species, &mut rng))
+      .collect();
+
+    let mut compute = ComputePass::init_with_particles(gpu, window_buffer, obstacle_buffer, config, particles, 0);
+    compute.rng = rng;
+    compute.spawned = config.particle_count as u64;
+    compute
+  }
+
+  /// Like `init`, but starts from the given particles instead of spawning new ones.
+  /// Particles added later continue the spawn sequence after the first `spawned` ones
+  pub fn init_with_particles(
+    gpu: &GpuWrapper,
+    window_buffer: &Buffer,
+    obstacle_buffer: &Buffer,
+    config: &SimConfig,
+    particles: Vec<Particle>,
+    spawned: u64,
+  ) -> ComputePass {
     let device = &gpu.device;
     let params_buffer = ComputePass::init_params_buffer(device);
+    let layout = ComputePass::init_bind_group_layout(device, &params_buffer);
+    let species = SpeciesBuffers::init(device, &config.species, &config.rules);
+    let obstacle_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+      label: Some("Obstacle bind group layout"),
+      entries: &[storage_layout_entry(0, true)],
+    });
+    let obstacle_bind_group = device.create_bind_group(&BindGroupDescriptor {
+      label: Some("Obstacle bind group"),
+      layout: &obstacle_layout,
+      entries: &[BindGroupEntry {
+        binding: 0,
+        resource: obstacle_buffer.as_entire_binding(),
+      }],
+    });
+    let pipeline = ComputePass::init_pipeline(device, &[&layout, &species.layout, &obstacle_layout]);
 
-    let count = 500;
-    let particles: Vec<Particle> = (0..count).map(|_| Particle::random()).collect();
-    let particle_buffer_a = ComputePass::init_particle_buffer(device, particles.clone());
-    let particle_buffer_b = ComputePass::init_particle_buffer(device, particles);
-
-    let layout = ComputePass::init_bind_group_layout(device, &params_buffer, &window_buffer, &particle_buffer_a, &particle_buffer_b);
-    let bind_group_a = ComputePass::init_bind_group_a(device, &params_buffer, &window_buffer, &particle_buffer_a, &particle_buffer_b, &layout);
-    let bind_group_b = ComputePass::init_bind_group_b(device, &params_buffer, &window_buffer, &particle_buffer_a, &particle_buffer_b, &layout);
+    let count = particles.len() as u32;
+    let capacity = ComputePass::capacity_for(device, count);
+    let storage = ParticleStorage::init(device, &layout, &params_buffer, window_buffer, capacity);
+    gpu.queue.write_buffer(&storage.particle_buffer_a, 0, bytemuck::cast_slice(&particles));
+    gpu.queue.write_buffer(&storage.particle_buffer_b, 0, bytemuck::cast_slice(&particles));
 
-    let pipeline = ComputePass::init_pipeline(device, &layout);
-    ComputePass {
+    let mut compute = ComputePass {
+      rules: config.rules,
+      interaction: Interaction::none(),
       params_buffer,
+      window_buffer: window_buffer.clone(),
+      layout,
       pipeline,
-      bind_group_a,
-      bind_group_b,
-      particle_buffer_a: particle_buffer_a,
-      particle_buffer_b: particle_buffer_b,
+      storage,
+      species,
+      obstacle_bind_group,
+      obstacle_count: 0,
+      boundary: config.boundary.clone(),
+      step_index: 0,
       particle_count: count,
       write_to_buffer_a: false,
-      last_run: None,
-    }
+      spawn: config.spawn.clone(),
+      rng: ComputePass::init_rng(config),
+      spawned: 0,
+    };
+    compute.restore_rng(config, spawned);
+    compute
   }
 
-  pub fn run(&mut self, encoder: &mut CommandEncoder, gpu: &GpuWrapper, window_count: u32) {
-    let queue = &gpu.queue;
-    self.update_params_buffer(queue, window_count);
+  /// Advances the particles by `dt` simulated seconds, `timestamp_writes` measure the step if given
+  pub fn run(
+    &mut self,
+    encoder: &mut CommandEncoder,
+    gpu: &GpuWrapper,
+    windows: &[Window],
+    dt: f32,
+    timestamp_writes: Option<ComputePassTimestampWrites>,
+  ) {
+    let params = self.params(windows, dt);
+    gpu.queue.write_buffer(&self.params_buffer, 0, bytemuck::bytes_of(&params));
+    self.step_index = self.step_index.wrapping_add(1);
+
+    // buffer a is the source when writing to b
+    let read_from_a = !self.write_to_buffer_a;
+    let storage = &self.storage;
+    storage.grid.clear(encoder);
 
     let mut cpass = encoder.begin_compute_pass(&ComputePassDescriptor {
       label: Some("Compute pass descriptor"),
-      timestamp_writes: None,
+      timestamp_writes,
     });
 
-    const WORKGROUP_SIZE: u32 = 64;
+    storage.grid.run(&mut cpass, read_from_a, self.particle_count);
 
     if self.write_to_buffer_a {
-      cpass.set_bind_group(0, &self.bind_group_b, &[]);
+      cpass.set_bind_group(0, &storage.bind_group_b, &[]);
     } else {
-      cpass.set_bind_group(0, &self.bind_group_a, &[]);
+      cpass.set_bind_group(0, &storage.bind_group_a, &[]);
     };
 
-    let workgroup_count = (self.particle_count + WORKGROUP_SIZE - 1) / WORKGROUP_SIZE;
+    let workgroup_count = self.particle_count.div_ceil(WORKGROUP_SIZE);
 
+    cpass.set_bind_group(1, &self.species.bind_group, &[]);
+    cpass.set_bind_group(2, &self.obstacle_bind_group, &[]);
     cpass.set_pipeline(&self.pipeline);
     cpass.dispatch_workgroups(workgroup_count, 1, 1);
 
     self.write_to_buffer_a = !self.write_to_buffer_a;
-    self.last_run = Some(Instant::now());
+  }
+
+  /// Params the next step runs with
+  pub fn params(&self, windows: &[Window], dt: f32) -> Params {
+    let mut params = Params::with_rules(dt, windows.len() as u32, self.particle_count, self.rules);
+    // species may react further out than the rules
+    params.cell_size = self.rules.max_radius().max(self.species.table.max_radius());
+    params.interaction = self.interaction;
+    params.obstacle_count = self.obstacle_count;
+    params.boundary = self.boundary.mode as u32;
+    params.step_index = self.step_index;
+    params.smoothing = self.boundary.smoothing;
+    (params.world_min, params.world_max) = match (self.boundary.world_min, self.boundary.world_max) {
+      (Some(min), Some(max)) => (min, max),
+      _ => bounding_box(windows),
+    };
+    params
+  }
+
+  /// Continues the step count of a snapshot, so open boundaries respawn the same way
+  pub fn set_step_index(&mut self, step_index: u32) {
+    self.step_index = step_index;
+  }
+
+  /// Respawns every particle the same way `init` does
+  pub fn reset(&mut self, queue: &Queue, config: &SimConfig) {
+    self.restore_rng(config, 0);
+    let particles = self.spawn(self.particle_count);
+    self.set_particles(queue, &particles);
+  }
+
+  /// Particles spawned since the rng was seeded, `restore_rng` continues from there
+  pub fn spawned(&self) -> u64 {
+    self.spawned
+  }
+
+  /// Reseeds the rng from `config` and skips the first `spawned` particles, so the next ones
+  /// are the same a run that never left off would have spawned
+  pub fn restore_rng(&mut self, config: &SimConfig, spawned: u64) {
+    self.rng = ComputePass::init_rng(config);
+    for _ in 0..spawned {
+      Particle::random(&self.spawn, &self.species.configs, &mut self.rng);
+    }
+    self.spawned = spawned;
+  }
+
+  /// Overwrites both ping-pong buffers, `particles` has to hold exactly `particle_count` particles
+  pub fn set_particles(&mut self, queue: &Queue, particles: &[Particle]) {
+    assert_eq!(
+      particles.len(),
+      self.particle_count as usize,
+      "use set_particle_count to change the count"
+    );
+    queue.write_buffer(&self.storage.particle_buffer_a, 0, bytemuck::cast_slice(particles));
+    queue.write_buffer(&self.storage.particle_buffer_b, 0, bytemuck::cast_slice(particles));
+  }
+
+  /// Grows or shrinks the population, existing particles keep their state and new ones are spawned like at startup.
+  /// Returns true if the particle buffers had to be reallocated, bind groups holding them have to be recreated then
+  pub fn set_particle_count(&mut self, gpu: &GpuWrapper, count: u32) -> bool {
+    let (device, queue) = (&gpu.device, &gpu.queue);
+    let count = count.clamp(1, ComputePass::max_particles(device));
+    let old_count = self.particle_count;
+
+    let reallocate = count > self.storage.capacity;
+    if reallocate {
+      let capacity = ComputePass::capacity_for(device, count);
+      let storage = ParticleStorage::init(device, &self.layout, &self.params_buffer, &self.window_buffer, capacity);
+
+      // latest state goes into both new buffers, so it doesn't matter which one is read first
+      let latest = self.latest_particle_buffer();
+      let copy_size = old_count as u64 * size_of::<Particle>() as u64;
+      let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor::default());
+      encoder.copy_buffer_to_buffer(latest, 0, &storage.particle_buffer_a, 0, copy_size);
+      encoder.copy_buffer_to_buffer(latest, 0, &storage.particle_buffer_b, 0, copy_size);
+      queue.submit(Some(encoder.finish()));
+
+      self.storage = storage;
+      self.write_to_buffer_a = false;
+    }
+
+    if count > old_count {
+      let spawned = self.spawn(count - old_count);
+      let offset = old_count as u64 * size_of::<Particle>() as u64;
+      queue.write_buffer(&self.storage.particle_buffer_a, offset, bytemuck::cast_slice(&spawned));
+      queue.write_buffer(&self.storage.particle_buffer_b, offset, bytemuck::cast_slice(&spawned));
+    }
+
+    self.particle_count = count;
+    reallocate
+  }
+
+  pub fn rules(&self) -> &BoidRules {
+    &self.rules
+  }
+
+  /// New rules are uploaded with the params of the next step, species falling back to them right away
+  pub fn set_rules(&mut self, queue: &Queue, rules: BoidRules) {
+    self.rules = rules;
+    self.species.update(queue, &rules);
+  }
+
+  /// The interaction is uploaded with the params of the next step
+  pub fn set_interaction(&mut self, interaction: Interaction) {
+    self.interaction = interaction;
+  }
+
+  /// Points the move pass at another windows buffer, e.g. after it had to grow
+  pub fn set_window_buffer(&mut self, device: &Device, window_buffer: &Buffer) {
+    self.window_buffer = window_buffer.clone();
+    self.storage.rebind(device, &self.layout, &self.params_buffer, window_buffer);
+  }
+
+  /// Number of obstacles at the start of the obstacle buffer that take part from the next step
+  pub fn set_obstacle_count(&mut self, obstacle_count: u32) {
+    self.obstacle_count = obstacle_count;
+  }
+
+  /// Starts copying the particles written by the latest step back to the CPU
+  pub fn readback(&self, gpu: &GpuWrapper) -> Readback<Particle> {
+    Readback::new(gpu, self.latest_particle_buffer(), self.particle_count as usize)
+  }
+
+  /// Blocks until the particles written by the latest step are copied back to the CPU
+  pub fn read_particles(&self, gpu: &GpuWrapper) -> Result<Vec<Particle>, ReadbackError> {
+    self.readback(gpu).wait()
   }
 
   pub fn get_particle_buffer(&self) -> (&Buffer, u32) {
     if self.write_to_buffer_a {
-      (&self.particle_buffer_a, self.particle_count)
+      (&self.storage.particle_buffer_a, self.particle_count)
     } else {
-      (&self.particle_buffer_b, self.particle_count)
+      (&self.storage.particle_buffer_b, self.particle_count)
+    }
+  }
+
+  /// Largest population the device can hold in a single storage binding
+  pub fn max_particles(device: &Device) -> u32 {
+    let limits = device.limits();
+    let max_bytes = (limits.max_storage_buffer_binding_size as u64).min(limits.max_buffer_size);
+    (max_bytes / size_of::<Particle>() as u64).min(u32::MAX as u64) as u32
+  }
+
+  fn latest_particle_buffer(&self) -> &Buffer {
+    // `write_to_buffer_a` already points at the buffer the next step writes to
+    if self.write_to_buffer_a {
+      &self.storage.particle_buffer_b
+    } else {
+      &self.storage.particle_buffer_a
+    }
+  }
+
+  fn capacity_for(device: &Device, count: u32) -> u32 {
+    let capacity = (count as f32 * CAPACITY_HEADROOM) as u32;
+    capacity.clamp(count.max(1), ComputePass::max_particles(device))
+  }
+
+  fn spawn(&mut self, count: u32) -> Vec<Particle> {
+    self.spawned += count as u64;
+    (0..count)
+      .map(|_| Particle::random(&self.spawn, &self.species.configs, &mut self.rng))
+      .collect()
+  }
+
+  fn init_rng(config: &SimConfig) -> StdRng {
+    match config.seed {
+      Some(seed) => StdRng::seed_from_u64(seed),
+      None => StdRng::from_os_rng(),
     }
   }
 
@@ -95,21 +353,7 @@ impl ComputePass {
     })
   }
 
-  fn init_particle_buffer(device: &Device, particles: Vec<Particle>) -> Buffer {
-    device.create_buffer_init(&BufferInitDescriptor {
-      label: Some("Particle buffer"),
-      contents: &bytemuck::cast_slice(&particles),
-      usage: BufferUsages::VERTEX | BufferUsages::STORAGE | BufferUsages::COPY_DST,
-    })
-  }
-
-  fn init_bind_group_layout(
-    device: &Device,
-    params_buffer: &Buffer,
-    window_buffer: &Buffer,
-    particle_buffer_a: &Buffer,
-    particle_buffer_b: &Buffer,
-  ) -> BindGroupLayout {
+  fn init_bind_group_layout(device: &Device, params_buffer: &Buffer) -> BindGroupLayout {
     device.create_bind_group_layout(&BindGroupLayoutDescriptor {
       label: Some("Compute bind group layout"),
       entries: &[
@@ -129,45 +373,32 @@ impl ComputePass {
           ty: BindingType::Buffer {
             ty: BufferBindingType::Storage { read_only: true },
             has_dynamic_offset: false,
-            min_binding_size: BufferSize::new(window_buffer.size()),
-          },
-          count: None,
-        },
-        BindGroupLayoutEntry {
-          binding: 2,
-          visibility: ShaderStages::COMPUTE,
-          ty: BindingType::Buffer {
-            ty: BufferBindingType::Storage { read_only: true },
-            has_dynamic_offset: false,
-            min_binding_size: BufferSize::new(particle_buffer_a.size()),
-          },
-          count: None,
-        },
-        BindGroupLayoutEntry {
-          binding: 3,
-          visibility: ShaderStages::COMPUTE,
-          ty: BindingType::Buffer {
-            ty: BufferBindingType::Storage { read_only: false },
-            has_dynamic_offset: false,
-            min_binding_size: BufferSize::new(particle_buffer_b.size()),
+            // the windows buffer grows with the number of windows, any size holding one fits
+            min_binding_size: BufferSize::new(size_of::<Window>() as u64),
           },
           count: None,
         },
+        storage_layout_entry(2, true),
+        storage_layout_entry(3, false),
+        storage_layout_entry(4, true),
+        storage_layout_entry(5, true),
+        storage_layout_entry(6, true),
       ],
     })
   }
 
-  fn init_bind_group_a(
+  fn init_bind_group(
     device: &Device,
+    label: &str,
+    layout: &BindGroupLayout,
     params_buffer: &Buffer,
     window_buffer: &Buffer,
-    particle_buffer_a: &Buffer,
-    particle_buffer_b: &Buffer,
-    layout: &BindGroupLayout,
+    (particle_src, particle_dst): (&Buffer, &Buffer),
+    grid: &SpatialGrid,
   ) -> BindGroup {
     device.create_bind_group(&BindGroupDescriptor {
-      label: Some("Compute bind group A"),
-      layout: layout,
+      label: Some(label),
+      layout,
       entries: &[
         BindGroupEntry {
           binding: 0,
@@ -179,54 +410,43 @@ impl ComputePass {
         },
         BindGroupEntry {
           binding: 2,
-          resource: particle_buffer_a.as_entire_binding(),
+          resource: particle_src.as_entire_binding(),
         },
         BindGroupEntry {
           binding: 3,
-          resource: particle_buffer_b.as_entire_binding(),
+          resource: particle_dst.as_entire_binding(),
         },
-      ],
-    })
-  }
-
-  fn init_bind_group_b(
-    device: &Device,
-    params_buffer: &Buffer,
-    window_buffer: &Buffer,
-    particle_buffer_a: &Buffer,
-    particle_buffer_b: &Buffer,
-    layout: &BindGroupLayout,
-  ) -> BindGroup {
-    device.create_bind_group(&BindGroupDescriptor {
-      label: Some("Compute bind group A"),
-      layout: layout,
-      entries: &[
         BindGroupEntry {
-          binding: 0,
-          resource: params_buffer.as_entire_binding(),
+          binding: 4,
+          resource: grid.bucket_starts.as_entire_binding(),
         },
         BindGroupEntry {
-          binding: 1,
-          resource: window_buffer.as_entire_binding(),
+          binding: 5,
+          resource: grid.sorted_particles.as_entire_binding(),
         },
         BindGroupEntry {
-          binding: 2,
-          resource: particle_buffer_b.as_entire_binding(),
-        },
-        BindGroupEntry {
-          binding: 3,
-          resource: particle_buffer_a.as_entire_binding(),
+          binding: 6,
+          resource: grid.sorted_indices.as_entire_binding(),
         },
       ],
     })
   }
 
-  fn init_pipeline(device: &Device, bind_group_layout: &BindGroupLayout) -> ComputePipeline {
-    let shader = device.create_shader_module(include_wgsl!("shaders/move.wgsl"));
+  fn init_pipeline(device: &Device, bind_group_layouts: &[&BindGroupLayout]) -> ComputePipeline {
+    let shader = init_shader(
+      device,
+      "Move shader",
+      concat!(
+        include_str!("shaders/params.wgsl"),
+        include_str!("shaders/cell.wgsl"),
+        include_str!("shaders/obstacle.wgsl"),
+        include_str!("shaders/move.wgsl")
+      ),
+    );
 
     let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
       label: Some("Compute pipeline layout"),
-      bind_group_layouts: &[&bind_group_layout],
+      bind_group_layouts,
       immediate_size: 0,
     });
 
@@ -239,14 +459,367 @@ impl ComputePass {
       cache: None,
     })
   }
+}
+
+impl SpeciesBuffers {
+  fn init(device: &Device, configs: &[SpeciesConfig], rules: &BoidRules) -> SpeciesBuffers {
+    let table = SpeciesTable::new(configs, rules);
+
+    let species_buffer = device.create_buffer_init(&BufferInitDescriptor {
+      label: Some("Species buffer"),
+      contents: bytemuck::cast_slice(&table.species),
+      usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
+    });
+    let weights_buffer = device.create_buffer_init(&BufferInitDescriptor {
+      label: Some("Species weights buffer"),
+      contents: bytemuck::cast_slice(&table.weights),
+      usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
+    });
+
+    let layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+      label: Some("Species bind group layout"),
+      entries: &[storage_layout_entry(0, true), storage_layout_entry(1, true)],
+    });
+    let bind_group = device.create_bind_group(&BindGroupDescriptor {
+      label: Some("Species bind group"),
+      layout: &layout,
+      entries: &[
+        BindGroupEntry {
+          binding: 0,
+          resource: species_buffer.as_entire_binding(),
+        },
+        BindGroupEntry {
+          binding: 1,
+          resource: weights_buffer.as_entire_binding(),
+        },
+      ],
+    });
+
+    SpeciesBuffers {
+      configs: configs.to_vec(),
+      table,
+      species_buffer,
+      weights_buffer,
+      layout,
+      bind_group,
+    }
+  }
+
+  /// Resolves the species against new rules, the species count stays the same so the buffers are reused
+  fn update(&mut self, queue: &Queue, rules: &BoidRules) {
+    self.table = SpeciesTable::new(&self.configs, rules);
+    queue.write_buffer(&self.species_buffer, 0, bytemuck::cast_slice(&self.table.species));
+    queue.write_buffer(&self.weights_buffer, 0, bytemuck::cast_slice(&self.table.weights));
+  }
+}
+
+impl ParticleStorage {
+  fn init(device: &Device, layout: &BindGroupLayout, params_buffer: &Buffer, window_buffer: &Buffer, capacity: u32) -> ParticleStorage {
+    let particle_buffer_a = ParticleStorage::init_particle_buffer(device, "Particle buffer A", capacity);
+    let particle_buffer_b = ParticleStorage::init_particle_buffer(device, "Particle buffer B", capacity);
+
+    let grid = SpatialGrid::init(device, params_buffer, &particle_buffer_a, &particle_buffer_b, capacity);
+
+    let (bind_group_a, bind_group_b) = ParticleStorage::init_bind_groups(
+      device,
+      layout,
+      params_buffer,
+      window_buffer,
+      (&particle_buffer_a, &particle_buffer_b),
+      &grid,
+    );
+
+    ParticleStorage {
+      capacity,
+      particle_buffer_a,
+      particle_buffer_b,
+      grid,
+      bind_group_a,
+      bind_group_b,
+    }
+  }
+
+  /// Recreates the bind groups around a new windows buffer, the particle buffers stay the same
+  fn rebind(&mut self, device: &Device, layout: &BindGroupLayout, params_buffer: &Buffer, window_buffer: &Buffer) {
+    (self.bind_group_a, self.bind_group_b) = ParticleStorage::init_bind_groups(
+      device,
+      layout,
+      params_buffer,
+      window_buffer,
+      (&self.particle_buffer_a, &self.particle_buffer_b),
+      &self.grid,
+    );
+  }
+
+  /// Bind group reading from a and writing to b, and the other way around
+  fn init_bind_groups(
+    device: &Device,
+    layout: &BindGroupLayout,
+    params_buffer: &Buffer,
+    window_buffer: &Buffer,
+    (particle_buffer_a, particle_buffer_b): (&Buffer, &Buffer),
+    grid: &SpatialGrid,
+  ) -> (BindGroup, BindGroup) {
+    let bind_group_a = ComputePass::init_bind_group(
+      device,
+      "Compute bind group A",
+      layout,
+      params_buffer,
+      window_buffer,
+      (particle_buffer_a, particle_buffer_b),
+      grid,
+    );
+    let bind_group_b = ComputePass::init_bind_group(
+      device,
+      "Compute bind group B",
+      layout,
+      params_buffer,
+      window_buffer,
+      (particle_buffer_b, particle_buffer_a),
+      grid,
+    );
+    (bind_group_a, bind_group_b)
+  }
+
+  fn init_particle_buffer(device: &Device, label: &str, capacity: u32) -> Buffer {
+    device.create_buffer(&BufferDescriptor {
+      label: Some(label),
+      size: capacity as u64 * size_of::<Particle>() as u64,
+      usage: BufferUsages::VERTEX | BufferUsages::STORAGE | BufferUsages::COPY_DST | BufferUsages::COPY_SRC,
+      mapped_at_creation: false,
+    })
+  }
+}
+
+/// Uniform grid that bins particles by cell before the force pass, so each particle
+/// only visits the 3x3 block of cells around it instead of every other particle.
+///
+/// Cells are hashed into a fixed number of buckets: particles are counted per bucket,
+/// the counts are prefix summed into bucket starts, particles are scattered into
+/// bucket order and placed by particle index within their bucket to keep runs deterministic.
+struct SpatialGrid {
+  assign_pipeline: ComputePipeline,
+  prefix_sum_pipeline: ComputePipeline,
+  scatter_pipeline: ComputePipeline,
+  rank_pipeline: ComputePipeline,
+  place_pipeline: ComputePipeline,
+
+  bind_group_a: BindGroup,
+  bind_group_b: BindGroup,
+
+  bucket_counts: Buffer,
+  bucket_starts: Buffer,
+  sorted_particles: Buffer,
+  sorted_indices: Buffer,
+}
+
+impl SpatialGrid {
+  /// Sized for `capacity` particles, only the first `params.particle_count` are binned
+  fn init(device: &Device, params_buffer: &Buffer, particle_buffer_a: &Buffer, particle_buffer_b: &Buffer, capacity: u32) -> SpatialGrid {
+    let bucket_count = capacity.max(1024).next_power_of_two() as u64;
+    let particle_count = capacity as u64;
+    let index_size = size_of::<u32>() as u64;
+
+    let particle_buckets = SpatialGrid::init_buffer(device, "Particle buckets buffer", particle_count * index_size);
+    let bucket_counts = SpatialGrid::init_buffer(device, "Bucket counts buffer", bucket_count * index_size);
+    let bucket_starts = SpatialGrid::init_buffer(device, "Bucket starts buffer", (bucket_count + 1) * index_size);
+    let bucket_offsets = SpatialGrid::init_buffer(device, "Bucket offsets buffer", bucket_count * index_size);
+    let sorted_particles = SpatialGrid::init_buffer(device, "Sorted particles buffer", particle_count * size_of::<Particle>() as u64);
+    let sorted_indices = SpatialGrid::init_buffer(device, "Sorted indices buffer", particle_count * index_size);
+
+    let layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+      label: Some("Grid bind group layout"),
+      entries: &[
+        storage_layout_entry(0, true),
+        storage_layout_entry(1, false),
+        storage_layout_entry(2, false),
+        storage_layout_entry(3, false),
+        storage_layout_entry(4, false),
+        storage_layout_entry(5, false),
+        storage_layout_entry(6, false),
+        BindGroupLayoutEntry {
+          binding: 7,
+          visibility: ShaderStages::COMPUTE,
+          ty: BindingType::Buffer {
+            ty: BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: BufferSize::new(params_buffer.size()),
+          },
+          count: None,
+        },
+      ],
+    });
+
+    let grid_buffers = [
+      &particle_buckets,
+      &bucket_counts,
+      &bucket_starts,
+      &bucket_offsets,
+      &sorted_particles,
+      &sorted_indices,
+    ];
+    let bind_group_a = SpatialGrid::init_bind_group(device, "Grid bind group A", &layout, params_buffer, particle_buffer_a, grid_buffers);
+    let bind_group_b = SpatialGrid::init_bind_group(device, "Grid bind group B", &layout, params_buffer, particle_buffer_b, grid_buffers);
 
-  fn update_params_buffer(&mut self, queue: &Queue, window_count: u32) {
-    let dt = match self.last_run {
-      Some(last) => last.elapsed().as_secs_f32(),
-      None => 0.0f32,
+    let shader = init_shader(
+      device,
+      "Grid shader",
+      concat!(
+        include_str!("shaders/params.wgsl"),
+        include_str!("shaders/cell.wgsl"),
+        include_str!("shaders/grid.wgsl")
+      ),
+    );
+    let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+      label: Some("Grid pipeline layout"),
+      bind_group_layouts: &[&layout],
+      immediate_size: 0,
+    });
+    let init_pipeline = |entry_point: &str| {
+      device.create_compute_pipeline(&ComputePipelineDescriptor {
+        label: Some(entry_point),
+        layout: Some(&pipeline_layout),
+        module: &shader,
+        entry_point: Some(entry_point),
+        compilation_options: Default::default(),
+        cache: None,
+      })
     };
 
-    let new_params = Params { dt, window_count };
-    queue.write_buffer(&self.params_buffer, 0, bytemuck::bytes_of(&new_params));
+    SpatialGrid {
+      assign_pipeline: init_pipeline("assign_cells"),
+      prefix_sum_pipeline: init_pipeline("prefix_sum"),
+      scatter_pipeline: init_pipeline("scatter"),
+      rank_pipeline: init_pipeline("rank_particles"),
+      place_pipeline: init_pipeline("place_indices"),
+      bind_group_a,
+      bind_group_b,
+      bucket_counts,
+      bucket_starts,
+      sorted_particles,
+      sorted_indices,
+    }
+  }
+
+  /// Resets bucket counts, has to be recorded outside of the compute pass
+  fn clear(&self, encoder: &mut CommandEncoder) {
+    encoder.clear_buffer(&self.bucket_counts, 0, None);
+  }
+
+  fn run(&self, cpass: &mut WgpuComputePass, read_from_a: bool, particle_count: u32) {
+    let bind_group = if read_from_a { &self.bind_group_a } else { &self.bind_group_b };
+    let workgroup_count = particle_count.div_ceil(WORKGROUP_SIZE);
+
+    cpass.set_bind_group(0, bind_group, &[]);
+
+    cpass.set_pipeline(&self.assign_pipeline);
+    cpass.dispatch_workgroups(workgroup_count, 1, 1);
+
+    cpass.set_pipeline(&self.prefix_sum_pipeline);
+    cpass.dispatch_workgroups(1, 1, 1);
+
+    cpass.set_pipeline(&self.scatter_pipeline);
+    cpass.dispatch_workgroups(workgroup_count, 1, 1);
+
+    cpass.set_pipeline(&self.rank_pipeline);
+    cpass.dispatch_workgroups(workgroup_count, 1, 1);
+
+    cpass.set_pipeline(&self.place_pipeline);
+    cpass.dispatch_workgroups(workgroup_count, 1, 1);
+  }
+
+  fn init_buffer(device: &Device, label: &str, size: u64) -> Buffer {
+    device.create_buffer(&BufferDescriptor {
+      label: Some(label),
+      size,
+      usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
+      mapped_at_creation: false,
+    })
+  }
+
+  fn init_bind_group(
+    device: &Device,
+    label: &str,
+    layout: &BindGroupLayout,
+    params_buffer: &Buffer,
+    particle_src: &Buffer,
+    grid_buffers: [&Buffer; 6],
+  ) -> BindGroup {
+    let mut entries = vec![
+      BindGroupEntry {
+        binding: 0,
+        resource: particle_src.as_entire_binding(),
+      },
+      BindGroupEntry {
+        binding: 7,
+        resource: params_buffer.as_entire_binding(),
+      },
+    ];
+    entries.extend(grid_buffers.iter().zip(1..).map(|(buffer, binding)| BindGroupEntry {
+      binding,
+      resource: buffer.as_entire_binding(),
+    }));
+
+    device.create_bind_group(&BindGroupDescriptor {
+      label: Some(label),
+      layout,
+      entries: &entries,
+    })
+  }
+}
+
+/// Smallest rectangle containing every window, empty when there are none
+fn bounding_box(windows: &[Window]) -> ([f32; 2], [f32; 2]) {
+  let Some(first) = windows.first() else {
+    return ([0.0, 0.0], [0.0, 0.0]);
+  };
+
+  windows.iter().fold((first.top_left, first.bottom_right), |(min, max), w| {
+    (
+      [min[0].min(w.top_left[0]), min[1].min(w.top_left[1])],
+      [max[0].max(w.bottom_right[0]), max[1].max(w.bottom_right[1])],
+    )
+  })
+}
+
+fn init_shader(device: &Device, label: &str, source: &'static str) -> ShaderModule {
+  device.create_shader_module(ShaderModuleDescriptor {
+    label: Some(label),
+    source: ShaderSource::Wgsl(source.into()),
+  })
+}
+
+fn storage_layout_entry(binding: u32, read_only: bool) -> BindGroupLayoutEntry {
+  BindGroupLayoutEntry {
+    binding,
+    visibility: ShaderStages::COMPUTE,
+    ty: BindingType::Buffer {
+      ty: BufferBindingType::Storage { read_only },
+      has_dynamic_offset: false,
+      min_binding_size: None,
+    },
+    count: None,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn bounding_box_covers_every_window() {
+    let windows = [
+      Window::new([100.0, 50.0], [200, 100]),
+      Window::new([-40.0, 300.0], [100, 100]),
+      Window::new([150.0, 80.0], [20, 20]),
+    ];
+
+    assert_eq!(bounding_box(&windows), ([-40.0, 50.0], [300.0, 400.0]));
+    assert_eq!(bounding_box(&windows[..1]), ([100.0, 50.0], [300.0, 150.0]));
+  }
+
+  #[test]
+  fn bounding_box_of_no_windows_is_empty() {
+    assert_eq!(bounding_box(&[]), ([0.0, 0.0], [0.0, 0.0]));
   }
 }
@@ -0,0 +1,705 @@
+//! CPU port of move.wgsl, kept line by line in sync with the shader so GPU results can be checked against it
+
+use crate::particle_sim::{
+  obstacle::Obstacle,
+  params::{BoundaryMode, Interaction, InteractionMode, Params},
+  particle::Particle,
+  species::SpeciesTable,
+  window::Window,
+};
+use std::collections::HashMap;
+
+type Vec2 = [f32; 2];
+
+/// Runs one simulation step exactly like the `main` entry point of move.wgsl.
+/// Neighbors are visited in the same order as on the GPU: the 3x3 cell block row by row, particles of a cell by index
+pub fn step_cpu(particles: &[Particle], windows: &[Window], params: &Params, species: &SpeciesTable, obstacles: &[Obstacle]) -> Vec<Particle> {
+  let mut cells: HashMap<[i32; 2], Vec<usize>> = HashMap::new();
+  for (index, particle) in particles.iter().enumerate() {
+    cells.entry(cell_coord(particle.pos, params.cell_size)).or_default().push(index);
+  }
+
+  let window_count = (params.window_count as usize).min(windows.len());
+  let windows = &windows[..window_count];
+
+  (0..particles.len())
+    .map(|id| step_particle(id, particles, &cells, windows, params, species, obstacles))
+    .collect()
+}
+
+fn step_particle(
+  id: usize,
+  particles: &[Particle],
+  cells: &HashMap<[i32; 2], Vec<usize>>,
+  windows: &[Window],
+  params: &Params,
+  table: &SpeciesTable,
+  obstacles: &[Obstacle],
+) -> Particle {
+  let rules = &params.rules;
+  let Particle {
+    mut pos,
+    mut vel,
+    mut color,
+    species: species_id,
+    ..
+  } = particles[id];
+  let own_species = table.index(species_id);
+  let species = &table.species[own_species];
+
+  let accel_force = scale(safe_normalize(vel), rules.accel_strength);
+
+  let fut_pos = add(pos, vel);
+  let (gradient, value) = sdf(fut_pos, windows, params);
+  let direction = scale(safe_normalize(gradient), -1.0);
+  let pulls_back = params.boundary == BoundaryMode::Soft as u32 || params.boundary == BoundaryMode::Bounce as u32;
+  let outside_force = match pulls_back {
+    true => scale(direction, rules.outside_strength * value.max(0.0)),
+    false => [0.0, 0.0],
+  };
+
+  let mut xenophobia = [0.0; 2];
+  let mut alignment = [0.0; 2];
+  let mut cohesion_far = [0.0; 2];
+  let mut cohesion_close = [0.0; 2];
+  let mut separation = [0.0; 2];
+  let mut color_source: i64 = -1;
+
+  let cell = cell_coord(pos, params.cell_size);
+  for dy in -1..=1 {
+    for dx in -1..=1 {
+      let Some(neighbors) = cells.get(&[cell[0] + dx, cell[1] + dy]) else {
+        continue;
+      };
+
+      for &other_index in neighbors {
+        let other = &particles[other_index];
+        let dist = length(sub(other.pos, pos));
+        let other_species = table.index(other.species);
+        let weights = table.weights(own_species, other_species);
+
+        if dist <= species.alignment_radius {
+          alignment = add(alignment, scale(other.vel, weights.alignment));
+        }
+
+        if dist <= species.cohesion_radius {
+          cohesion_far = add(cohesion_far, scale(sub(other.pos, pos), weights.cohesion));
+        }
+
+        if dist <= rules.cohesion_close_radius {
+          cohesion_close = add(cohesion_close, scale(sub(other.pos, pos), weights.cohesion));
+
+          let angle_dif = dot(safe_normalize(vel), safe_normalize(other.vel));
+          if other_species == own_species
+            && length(vel) <= length(other.vel)
+            && angle_dif > rules.contagion_min_cos
+            && other_index as i64 > color_source
+          {
+            color = other.color;
+            color_source = other_index as i64;
+          }
+        }
+
+        if dist <= species.separation_radius {
+          separation = add(separation, scale(sub(pos, other.pos), weights.separation));
+        }
+
+        if dist >= rules.xenophobia_start_radius && dist <= rules.xenophobia_end_radius {
+          xenophobia = add(xenophobia, sub(pos, other.pos));
+        }
+      }
+    }
+  }
+
+  let forces = [
+    outside_force,
+    accel_force,
+    scale(safe_normalize(alignment), rules.alignment_strength),
+    scale(safe_normalize(cohesion_far), rules.cohesion_far_strength),
+    scale(safe_normalize(cohesion_close), rules.cohesion_close_strength),
+    scale(safe_normalize(separation), rules.separation_strength),
+    scale(safe_normalize(xenophobia), rules.xenophobia_strength),
+    interaction_force(pos, &params.interaction),
+    obstacle_force(pos, params, obstacles),
+  ];
+  let total_force = forces.into_iter().reduce(add).unwrap();
+
+  vel = add(vel, scale(total_force, params.dt));
+  let speed = length(vel);
+  vel = scale(safe_normalize(vel), speed.clamp(0.0, species.max_speed));
+
+  let old_pos = pos;
+  pos = add(pos, scale(vel, params.dt));
+
+  match params.boundary {
+    b if b == BoundaryMode::Wrap as u32 => pos = wrap(pos, params),
+    b if b == BoundaryMode::Bounce as u32 => bounce(old_pos, &mut pos, &mut vel, windows, params),
+    b if b == BoundaryMode::Open as u32 && sdf(pos, windows, params).1 > 0.0 => respawn(id as u32, &mut pos, &mut vel, windows, params),
+    _ => (),
+  }
+
+  Particle::new(pos, vel, color, species_id)
+}
+
+/// Gradient and signed distance of the simulated area: the world rectangle when wrapping,
+/// the union of all windows otherwise, blended over `params.smoothing`; 1000 when there are no windows
+fn sdf(p: Vec2, windows: &[Window], params: &Params) -> (Vec2, f32) {
+  if params.boundary == BoundaryMode::Wrap as u32 {
+    return rect_sdf(p, params.world_min, params.world_max);
+  }
+
+  windows
+    .iter()
+    .map(|w| rect_sdf(p, w.top_left, w.bottom_right))
+    .reduce(|a, b| smooth_min(a, b, params.smoothing))
+    .unwrap_or(([0.0, 0.0], 1000.0))
+}
+
+fn rect_sdf(p: Vec2, top_left: Vec2, bottom_right: Vec2) -> (Vec2, f32) {
+  let center = scale(add(top_left, bottom_right), 0.5);
+  let half_size = scale(sub(bottom_right, top_left), 0.5);
+  let d = sub([(p[0] - center[0]).abs(), (p[1] - center[1]).abs()], half_size);
+  let outside = [d[0].max(0.0), d[1].max(0.0)];
+  let value = length(outside) + d[0].max(d[1]).min(0.0);
+
+  let direction = match value > 0.0 {
+    true => scale(outside, 1.0 / length(outside)),
+    false if d[0] > d[1] => [1.0, 0.0],
+    false => [0.0, 1.0],
+  };
+  ([sign(p[0] - center[0]) * direction[0], sign(p[1] - center[1]) * direction[1]], value)
+}
+
+fn smooth_min(a: (Vec2, f32), b: (Vec2, f32), k: f32) -> (Vec2, f32) {
+  if k <= 0.0 {
+    return if a.1 < b.1 { a } else { b };
+  }
+
+  let t = (0.5 + 0.5 * (b.1 - a.1) / k).clamp(0.0, 1.0);
+  let value = mix(b.1, a.1, t) - k * t * (1.0 - t);
+  ([mix(b.0[0], a.0[0], t), mix(b.0[1], a.0[1], t)], value)
+}
+
+fn wrap(p: Vec2, params: &Params) -> Vec2 {
+  let size = sub(params.world_max, params.world_min);
+  if size[0] <= 0.0 || size[1] <= 0.0 {
+    return p;
+  }
+  let wrap_axis = |axis: usize| p[axis] - size[axis] * ((p[axis] - params.world_min[axis]) / size[axis]).floor();
+  [wrap_axis(0), wrap_axis(1)]
+}
+
+fn bounce(old_pos: Vec2, pos: &mut Vec2, vel: &mut Vec2, windows: &[Window], params: &Params) {
+  if sdf(*pos, windows, params).1 <= 0.0 {
+    return;
+  }
+
+  let Some(w) = windows.iter().find(|w| rect_sdf(old_pos, w.top_left, w.bottom_right).1 <= 0.0) else {
+    return;
+  };
+
+  for axis in 0..2 {
+    if pos[axis] < w.top_left[axis] {
+      pos[axis] = 2.0 * w.top_left[axis] - pos[axis];
+      vel[axis] = vel[axis].abs();
+    } else if pos[axis] > w.bottom_right[axis] {
+      pos[axis] = 2.0 * w.bottom_right[axis] - pos[axis];
+      vel[axis] = -vel[axis].abs();
+    }
+  }
+}
+
+fn respawn(id: u32, pos: &mut Vec2, vel: &mut Vec2, windows: &[Window], params: &Params) {
+  if windows.is_empty() {
+    return;
+  }
+
+  let mut seed = pcg_hash(id ^ pcg_hash(params.step_index));
+  let w = &windows[(seed % windows.len() as u32) as usize];
+
+  let offset = [random_float(&mut seed), random_float(&mut seed)];
+  let angle = random_float(&mut seed) * std::f32::consts::TAU;
+
+  *pos = add(
+    w.top_left,
+    [
+      (w.bottom_right[0] - w.top_left[0]) * offset[0],
+      (w.bottom_right[1] - w.top_left[1]) * offset[1],
+    ],
+  );
+  *vel = scale([angle.cos(), angle.sin()], length(*vel));
+}
+
+fn pcg_hash(v: u32) -> u32 {
+  let state = v.wrapping_mul(747796405).wrapping_add(2891336453);
+  let word = ((state >> ((state >> 28) + 4)) ^ state).wrapping_mul(277803737);
+  (word >> 22) ^ word
+}
+
+fn random_float(seed: &mut u32) -> f32 {
+  *seed = pcg_hash(*seed);
+  (*seed >> 8) as f32 / 16777216.0
+}
+
+fn interaction_force(pos: Vec2, interaction: &Interaction) -> Vec2 {
+  let offset = sub(interaction.pos, pos);
+  let dist = length(offset);
+  if interaction.mode == InteractionMode::None as u32 || dist > interaction.radius {
+    return [0.0, 0.0];
+  }
+
+  let direction = safe_normalize(offset);
+  let force = match interaction.mode {
+    m if m == InteractionMode::Attract as u32 => direction,
+    m if m == InteractionMode::Repel as u32 => scale(direction, -1.0),
+    m if m == InteractionMode::Vortex as u32 => [-direction[1], direction[0]],
+    _ => [0.0, 0.0],
+  };
+
+  scale(force, interaction.strength * (1.0 - dist / interaction.radius))
+}
+
+fn obstacle_force(pos: Vec2, params: &Params, obstacles: &[Obstacle]) -> Vec2 {
+  let rules = &params.rules;
+  let obstacle_count = (params.obstacle_count as usize).min(obstacles.len());
+
+  let mut force = [0.0, 0.0];
+  for obstacle in &obstacles[..obstacle_count] {
+    let d = obstacle.sdf(pos);
+    if d < rules.obstacle_distance {
+      force = add(force, scale(obstacle_gradient(obstacle, pos), 1.0 - d.max(0.0) / rules.obstacle_distance));
+    }
+  }
+
+  scale(force, rules.obstacle_strength)
+}
+
+fn obstacle_gradient(obstacle: &Obstacle, pos: Vec2) -> Vec2 {
+  let gradient = [
+    obstacle.sdf(add(pos, [1.0, 0.0])) - obstacle.sdf(sub(pos, [1.0, 0.0])),
+    obstacle.sdf(add(pos, [0.0, 1.0])) - obstacle.sdf(sub(pos, [0.0, 1.0])),
+  ];
+  safe_normalize(gradient)
+}
+
+fn cell_coord(p: Vec2, cell_size: f32) -> [i32; 2] {
+  [(p[0] / cell_size).floor() as i32, (p[1] / cell_size).floor() as i32]
+}
+
+fn safe_normalize(v: Vec2) -> Vec2 {
+  let len = length(v);
+  if len > 0.0001 { [v[0] / len, v[1] / len] } else { [0.0, 0.0] }
+}
+
+fn add(a: Vec2, b: Vec2) -> Vec2 {
+  [a[0] + b[0], a[1] + b[1]]
+}
+
+fn sub(a: Vec2, b: Vec2) -> Vec2 {
+  [a[0] - b[0], a[1] - b[1]]
+}
+
+fn scale(v: Vec2, s: f32) -> Vec2 {
+  [v[0] * s, v[1] * s]
+}
+
+/// WGSL `mix`
+fn mix(a: f32, b: f32, t: f32) -> f32 {
+  a * (1.0 - t) + b * t
+}
+
+/// WGSL `sign`, unlike `f32::signum` it is 0 at 0
+fn sign(v: f32) -> f32 {
+  if v > 0.0 {
+    1.0
+  } else if v < 0.0 {
+    -1.0
+  } else {
+    0.0
+  }
+}
+
+fn dot(a: Vec2, b: Vec2) -> f32 {
+  a[0] * b[0] + a[1] * b[1]
+}
+
+fn length(v: Vec2) -> f32 {
+  dot(v, v).sqrt()
+}
+
+#[cfg(test)]
+pub(super) mod tests {
+  use super::*;
+  use crate::{
+    app::gpu_wrapper::GpuWrapper,
+    config::{BoundaryConfig, GpuConfig, SimConfig, SpawnConfig, SpeciesConfig},
+    particle_sim::{obstacle::ObstacleShape, params::BoidRules, particle_sim::ParticleSim},
+  };
+  use rand::{SeedableRng, rngs::StdRng};
+  use wgpu::{CommandEncoderDescriptor, TextureFormat};
+
+  const DT: f32 = 1.0 / 60.0;
+  const POS_TOLERANCE: f32 = 1e-3;
+  const VEL_TOLERANCE: f32 = 1e-2;
+
+  /// Software adapter if there is one, any adapter otherwise; fails the test without an adapter
+  pub(crate) fn test_gpu() -> GpuWrapper {
+    let fallback = GpuConfig {
+      force_fallback_adapter: true,
+      ..GpuConfig::default()
+    };
+
+    futures::executor::block_on(GpuWrapper::new(&fallback))
+      .or_else(|_| futures::executor::block_on(GpuWrapper::new(&GpuConfig::default())))
+      .expect("GPU comparison tests need an adapter, a software one is enough")
+  }
+
+  /// Everything besides particles and windows that a step depends on
+  struct Scene {
+    interaction: Interaction,
+    species: Vec<SpeciesConfig>,
+    obstacles: Vec<ObstacleShape>,
+    boundary: BoundaryConfig,
+  }
+
+  impl Default for Scene {
+    fn default() -> Scene {
+      Scene {
+        interaction: Interaction::none(),
+        species: vec![SpeciesConfig::default()],
+        obstacles: Vec::new(),
+        boundary: BoundaryConfig::default(),
+      }
+    }
+  }
+
+  fn spawn(count: u32, spawn: SpawnConfig, species: &[SpeciesConfig], seed: u64) -> Vec<Particle> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    (0..count).map(|_| Particle::random(&spawn, species, &mut rng)).collect()
+  }
+
+  /// Three species chasing each other in a circle, each with its own speed and radii
+  fn rock_paper_scissors() -> Vec<SpeciesConfig> {
+    let species = |name: &str, max_speed: f32, radius: f32, cohesion: [f32; 3]| SpeciesConfig {
+      name: String::from(name),
+      max_speed: Some(max_speed),
+      cohesion_radius: Some(radius),
+      separation_radius: Some(radius / 10.0),
+      cohesion: cohesion.to_vec(),
+      separation: vec![1.0, 2.0, 0.5],
+      ..SpeciesConfig::default()
+    };
+
+    vec![
+      species("rock", 40.0, 120.0, [1.0, 2.0, -2.0]),
+      species("paper", 50.0, 180.0, [-2.0, 1.0, 2.0]),
+      species("scissors", 60.0, 300.0, [2.0, -2.0, 1.0]),
+    ]
+  }
+
+  fn default_species() -> SpeciesTable {
+    SpeciesTable::new(&[SpeciesConfig::default()], &BoidRules::default())
+  }
+
+  fn cpu_params(particle_count: usize, table: &SpeciesTable) -> Params {
+    let rules = BoidRules::default();
+    let mut params = Params::with_rules(DT, 1, particle_count as u32, rules);
+    params.cell_size = rules.max_radius().max(table.max_radius());
+    params
+  }
+
+  fn step_gpu(gpu: &GpuWrapper, particles: &[Particle], windows: &[Window], scene: &Scene) -> Vec<Particle> {
+    let config = SimConfig {
+      particle_count: particles.len() as u32,
+      species: scene.species.clone(),
+      obstacles: scene.obstacles.clone(),
+      boundary: scene.boundary.clone(),
+      ..SimConfig::default()
+    };
+    let mut sim = ParticleSim::init_with_particles(gpu, TextureFormat::Rgba8UnormSrgb, &config, particles.to_vec(), 0);
+    sim.set_interaction(scene.interaction);
+
+    let mut encoder = gpu.device.create_command_encoder(&CommandEncoderDescriptor::default());
+    sim.compute(&mut encoder, gpu, windows.to_vec(), DT, None);
+    gpu.queue.submit(Some(encoder.finish()));
+
+    sim.read_particles(gpu).expect("Failed to read particles back")
+  }
+
+  fn assert_matches(name: &str, gpu: &[Particle], cpu: &[Particle]) {
+    assert_eq!(gpu.len(), cpu.len(), "{name}: particle count differs");
+
+    for (i, (g, c)) in gpu.iter().zip(cpu).enumerate() {
+      let pos_err = length(sub(g.pos, c.pos));
+      let vel_err = length(sub(g.vel, c.vel));
+      assert!(pos_err <= POS_TOLERANCE, "{name}, particle {i}: gpu pos {:?}, cpu pos {:?}", g.pos, c.pos);
+      assert!(vel_err <= VEL_TOLERANCE, "{name}, particle {i}: gpu vel {:?}, cpu vel {:?}", g.vel, c.vel);
+      assert_eq!(g.color, c.color, "{name}, particle {i}: color differs");
+    }
+  }
+
+  fn compare(name: &str, particles: &[Particle], windows: &[Window], scene: &Scene) {
+    let gpu = test_gpu();
+
+    let table = SpeciesTable::new(&scene.species, &BoidRules::default());
+    let obstacles: Vec<Obstacle> = scene.obstacles.iter().map(Obstacle::from).collect();
+    let mut params = cpu_params(particles.len(), &table);
+    params.window_count = windows.len() as u32;
+    params.interaction = scene.interaction;
+    params.obstacle_count = obstacles.len() as u32;
+    params.boundary = scene.boundary.mode as u32;
+    params.world_min = scene.boundary.world_min.unwrap_or_default();
+    params.world_max = scene.boundary.world_max.unwrap_or_default();
+    params.smoothing = scene.boundary.smoothing;
+
+    let cpu = step_cpu(particles, windows, &params, &table, &obstacles);
+    let gpu = step_gpu(&gpu, particles, windows, scene);
+
+    assert_matches(name, &gpu, &cpu);
+  }
+
+  #[test]
+  fn gpu_step_matches_cpu_inside_window() {
+    let particles = spawn(512, SpawnConfig::default(), &[SpeciesConfig::default()], 1);
+    let windows = [Window::new([0.0, 0.0], [1024, 1024])];
+
+    compare("inside window", &particles, &windows, &Scene::default());
+  }
+
+  #[test]
+  fn gpu_step_matches_cpu_outside_windows() {
+    let spawn_config = SpawnConfig {
+      pos_min: [-500.0, -500.0],
+      pos_max: [1500.0, 1500.0],
+      max_velocity: 50.0,
+    };
+    let particles = spawn(512, spawn_config, &[SpeciesConfig::default()], 2);
+    let windows = [Window::new([0.0, 0.0], [400, 300]), Window::new([600.0, 500.0], [300, 400])];
+
+    compare("outside windows", &particles, &windows, &Scene::default());
+  }
+
+  /// One scene per feature of the step, each against the spawn and windows that exercise it
+  #[test]
+  fn gpu_step_matches_cpu_in_every_scene() {
+    let window = [Window::new([0.0, 0.0], [1024, 1024])];
+    let spread = |pos_min: [f32; 2], pos_max: [f32; 2], max_velocity: f32| SpawnConfig {
+      pos_min,
+      pos_max,
+      max_velocity,
+    };
+
+    let mut scenes: Vec<(&str, SpawnConfig, Vec<Window>, Scene)> = Vec::new();
+    for mode in [InteractionMode::Attract, InteractionMode::Repel, InteractionMode::Vortex] {
+      let scene = Scene {
+        interaction: Interaction::new([512.0, 512.0], mode, 300.0, 80.0),
+        ..Scene::default()
+      };
+      scenes.push(("interaction", SpawnConfig::default(), window.to_vec(), scene));
+    }
+
+    let scene = Scene {
+      species: rock_paper_scissors(),
+      ..Scene::default()
+    };
+    scenes.push(("species", SpawnConfig::default(), window.to_vec(), scene));
+
+    let scene = Scene {
+      obstacles: vec![
+        ObstacleShape::Circle {
+          center: [200.0, 200.0],
+          radius: 80.0,
+        },
+        ObstacleShape::Box {
+          min: [600.0, 100.0],
+          max: [900.0, 250.0],
+        },
+        ObstacleShape::Capsule {
+          a: [150.0, 700.0],
+          b: [450.0, 850.0],
+          radius: 30.0,
+        },
+        ObstacleShape::Polygon {
+          points: vec![[600.0, 600.0], [900.0, 650.0], [750.0, 700.0], [800.0, 900.0]],
+        },
+      ],
+      ..Scene::default()
+    };
+    scenes.push(("obstacles", SpawnConfig::default(), window.to_vec(), scene));
+
+    // packed around the window edges, so plenty of particles cross them in one step
+    for mode in [BoundaryMode::Soft, BoundaryMode::Wrap, BoundaryMode::Bounce, BoundaryMode::Open] {
+      let scene = Scene {
+        boundary: BoundaryConfig {
+          mode,
+          world_min: Some([0.0, 0.0]),
+          world_max: Some([400.0, 200.0]),
+          smoothing: 0.0,
+        },
+        ..Scene::default()
+      };
+      let windows = vec![Window::new([0.0, 0.0], [200, 200]), Window::new([300.0, 0.0], [100, 100])];
+      scenes.push(("boundary", spread([-20.0, -20.0], [220.0, 220.0], 600.0), windows, scene));
+    }
+
+    // overlapping, touching and separate windows
+    for smoothing in [0.0, 80.0] {
+      let scene = Scene {
+        boundary: BoundaryConfig {
+          smoothing,
+          ..BoundaryConfig::default()
+        },
+        ..Scene::default()
+      };
+      let windows = vec![
+        Window::new([0.0, 0.0], [300, 200]),
+        Window::new([200.0, 100.0], [200, 300]),
+        Window::new([400.0, 0.0], [100, 100]),
+        Window::new([550.0, 450.0], [100, 100]),
+      ];
+      scenes.push(("window union", spread([-300.0, -300.0], [900.0, 900.0], 50.0), windows, scene));
+    }
+
+    // more than the windows buffer starts with room for, so it has to grow
+    let windows = (0..20)
+      .map(|i| Window::new([(i % 10) as f32 * 100.0, (i / 10) as f32 * 250.0], [80, 200]))
+      .collect();
+    scenes.push(("many windows", spread([-100.0, -100.0], [1100.0, 600.0], 50.0), windows, Scene::default()));
+
+    for (seed, (name, spawn_config, windows, scene)) in scenes.into_iter().enumerate() {
+      let particles = spawn(512, spawn_config, &scene.species, seed as u64);
+      compare(name, &particles, &windows, &scene);
+    }
+  }
+
+  #[test]
+  fn speed_is_clamped() {
+    let particles = [Particle::new([10.0, 10.0], [1000.0, -1000.0], [1.0; 4], 0)];
+    let windows = [Window::new([0.0, 0.0], [100, 100])];
+    let params = Params::with_rules(DT, 1, 1, BoidRules::default());
+
+    let stepped = step_cpu(&particles, &windows, &params, &default_species(), &[]);
+    assert!(length(stepped[0].vel) <= params.rules.max_speed * (1.0 + f32::EPSILON));
+  }
+
+  #[test]
+  fn outside_particle_is_pulled_towards_window() {
+    let particles = [Particle::new([500.0, 50.0], [0.0, 0.0], [1.0; 4], 0)];
+    let windows = [Window::new([0.0, 0.0], [100, 100])];
+    let params = Params::with_rules(DT, 1, 1, BoidRules::default());
+
+    let stepped = step_cpu(&particles, &windows, &params, &default_species(), &[]);
+    assert!(stepped[0].vel[0] < 0.0);
+  }
+
+  #[test]
+  fn repel_pushes_particle_away_from_cursor() {
+    let particles = [Particle::new([50.0, 50.0], [0.0, 0.0], [1.0; 4], 0)];
+    let windows = [Window::new([0.0, 0.0], [100, 100])];
+    let mut params = Params::with_rules(DT, 1, 1, BoidRules::default());
+    params.interaction = Interaction::new([40.0, 50.0], InteractionMode::Repel, 50.0, 100.0);
+
+    let stepped = step_cpu(&particles, &windows, &params, &default_species(), &[]);
+    assert!(stepped[0].vel[0] > 0.0);
+  }
+
+  #[test]
+  fn prey_flees_predator() {
+    let species = [
+      SpeciesConfig {
+        name: String::from("prey"),
+        cohesion: vec![1.0, -1.0],
+        ..SpeciesConfig::default()
+      },
+      SpeciesConfig {
+        name: String::from("predator"),
+        cohesion: vec![1.0, 1.0],
+        ..SpeciesConfig::default()
+      },
+    ];
+    let table = SpeciesTable::new(&species, &BoidRules::default());
+    let particles = [
+      Particle::new([500.0, 500.0], [0.0, 0.0], [1.0; 4], 0),
+      Particle::new([550.0, 500.0], [0.0, 0.0], [1.0; 4], 1),
+    ];
+    let windows = [Window::new([0.0, 0.0], [1024, 1024])];
+
+    let stepped = step_cpu(&particles, &windows, &cpu_params(particles.len(), &table), &table, &[]);
+    assert!(stepped[0].vel[0] < 0.0, "prey should move away");
+    assert!(stepped[1].vel[0] < 0.0, "predator should follow");
+  }
+
+  #[test]
+  fn particle_steers_away_from_obstacle() {
+    let particles = [Particle::new([100.0, 50.0], [0.0, 0.0], [1.0; 4], 0)];
+    let windows = [Window::new([0.0, 0.0], [1024, 1024])];
+    let obstacles = [Obstacle::from(&ObstacleShape::Circle {
+      center: [130.0, 50.0],
+      radius: 20.0,
+    })];
+    let mut params = Params::with_rules(DT, 1, 1, BoidRules::default());
+    params.obstacle_count = 1;
+
+    let stepped = step_cpu(&particles, &windows, &params, &default_species(), &obstacles);
+    assert!(stepped[0].vel[0] < 0.0);
+  }
+
+  #[test]
+  fn wrapped_particle_enters_on_opposite_side() {
+    let particles = [Particle::new([99.9, 50.0], [5.0, 0.0], [1.0; 4], 0)];
+    let windows = [Window::new([0.0, 0.0], [100, 100])];
+    let mut params = Params::with_rules(1.0, 1, 1, BoidRules::default());
+    params.boundary = BoundaryMode::Wrap as u32;
+    params.world_max = [100.0, 100.0];
+
+    let stepped = step_cpu(&particles, &windows, &params, &default_species(), &[]);
+    assert!(stepped[0].pos[0] < 50.0, "wrapped to {:?}", stepped[0].pos);
+  }
+
+  #[test]
+  fn bounced_particle_turns_around() {
+    let particles = [Particle::new([99.9, 50.0], [5.0, 0.0], [1.0; 4], 0)];
+    let windows = [Window::new([0.0, 0.0], [100, 100])];
+    let mut params = Params::with_rules(1.0, 1, 1, BoidRules::default());
+    params.boundary = BoundaryMode::Bounce as u32;
+
+    let stepped = step_cpu(&particles, &windows, &params, &default_species(), &[]);
+    assert!(stepped[0].pos[0] <= 100.0);
+    assert!(stepped[0].vel[0] < 0.0);
+  }
+
+  #[test]
+  fn smooth_min_without_smoothing_is_min() {
+    let a = ([1.0, 0.0], 3.0);
+    let b = ([0.0, 1.0], 5.0);
+
+    assert_eq!(smooth_min(a, b, 0.0), a);
+    assert_eq!(smooth_min(b, a, 0.0), a);
+  }
+
+  #[test]
+  fn smooth_min_blends_close_distances() {
+    let a = ([1.0, 0.0], 2.0);
+    let b = ([0.0, 1.0], 2.0);
+
+    // equal distances meet halfway, a quarter of `k` below both
+    let (gradient, value) = smooth_min(a, b, 8.0);
+    assert_eq!(gradient, [0.5, 0.5]);
+    assert!(value.abs() < 1e-6, "{value}");
+
+    // further apart than `k` the closer one wins unchanged
+    assert_eq!(smooth_min(a, ([0.0, 1.0], 20.0), 8.0), a);
+  }
+
+  #[test]
+  fn outside_force_points_to_closest_edge_of_window_union() {
+    // above the seam of two windows side by side, the closest edge is straight below
+    let particles = [Particle::new([90.0, -50.0], [0.0, 0.0], [1.0; 4], 0)];
+    let windows = [Window::new([0.0, 0.0], [100, 100]), Window::new([100.0, 0.0], [100, 100])];
+    let params = Params::with_rules(DT, 2, 1, BoidRules::default());
+
+    let stepped = step_cpu(&particles, &windows, &params, &default_species(), &[]);
+    assert!(stepped[0].vel[0].abs() < 1e-4, "pulled sideways: {:?}", stepped[0].vel);
+    assert!(stepped[0].vel[1] > 0.0);
+  }
+}
@@ -1,6 +1,15 @@
 pub mod compute_pass;
+// reference for validating the GPU step, only the tests call it
+#[cfg(test)]
+mod cpu;
+pub mod obstacle;
 pub mod params;
 pub mod particle;
+#[allow(clippy::module_inception)]
 pub mod particle_sim;
+pub mod readback;
 pub mod render_pass;
+pub mod snapshot;
+pub mod species;
+pub mod view;
 pub mod window;
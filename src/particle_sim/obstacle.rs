@@ -0,0 +1,253 @@
+use bytemuck::{Pod, Zeroable};
+use serde::{Deserialize, Serialize};
+
+/// Most corners a polygon obstacle can have
+pub const MAX_POLYGON_POINTS: usize = 16;
+
+/// Obstacle shape in desktop coordinates, as written in the config
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "shape", rename_all = "snake_case", deny_unknown_fields)]
+pub enum ObstacleShape {
+  Circle {
+    center: [f32; 2],
+    radius: f32,
+  },
+  Box {
+    min: [f32; 2],
+    max: [f32; 2],
+  },
+  /// segment from `a` to `b` widened by `radius`
+  Capsule {
+    a: [f32; 2],
+    b: [f32; 2],
+    radius: f32,
+  },
+  /// corners in order, at most `MAX_POLYGON_POINTS`
+  Polygon {
+    points: Vec<[f32; 2]>,
+  },
+}
+
+/// Obstacle as uploaded to the GPU, mirrored by `Obstacle` in obstacle.wgsl
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Pod, Zeroable)]
+pub struct Obstacle {
+  /// 0 circle, 1 box, 2 capsule, 3 polygon
+  pub kind: u32,
+  pub point_count: u32,
+  pub radius: f32,
+  _padding: f32,
+  /// circle: center; box: min and max; capsule: both ends; polygon: corners
+  pub points: [[f32; 2]; MAX_POLYGON_POINTS],
+}
+
+impl Obstacle {
+  fn new(kind: u32, points: &[[f32; 2]], radius: f32) -> Obstacle {
+    let mut obstacle = Obstacle {
+      kind,
+      point_count: points.len().min(MAX_POLYGON_POINTS) as u32,
+      radius,
+      _padding: 0.0,
+      points: [[0.0; 2]; MAX_POLYGON_POINTS],
+    };
+    obstacle.points[..obstacle.point_count as usize].copy_from_slice(&points[..obstacle.point_count as usize]);
+    obstacle
+  }
+
+  /// Signed distance from `p` to the obstacle, negative inside; mirrors `obstacle_sdf` in obstacle.wgsl
+  pub fn sdf(&self, p: [f32; 2]) -> f32 {
+    let points = &self.points[..self.point_count as usize];
+    match self.kind {
+      0 => length(sub(p, points[0])) - self.radius,
+      1 => {
+        let center = scale(add(points[0], points[1]), 0.5);
+        let half_size = scale(sub(points[1], points[0]), 0.5);
+        let d = sub([(p[0] - center[0]).abs(), (p[1] - center[1]).abs()], half_size);
+        length([d[0].max(0.0), d[1].max(0.0)]) + d[0].max(d[1]).min(0.0)
+      }
+      2 => segment_distance(p, points[0], points[1]) - self.radius,
+      _ => polygon_sdf(p, points),
+    }
+  }
+}
+
+impl From<&ObstacleShape> for Obstacle {
+  fn from(shape: &ObstacleShape) -> Obstacle {
+    match shape {
+      ObstacleShape::Circle { center, radius } => Obstacle::new(0, &[*center], *radius),
+      ObstacleShape::Box { min, max } => Obstacle::new(1, &[*min, *max], 0.0),
+      ObstacleShape::Capsule { a, b, radius } => Obstacle::new(2, &[*a, *b], *radius),
+      ObstacleShape::Polygon { points } => Obstacle::new(3, points, 0.0),
+    }
+  }
+}
+
+/// Back from the GPU layout, e.g. for obstacles stored in a snapshot
+impl From<&Obstacle> for ObstacleShape {
+  fn from(obstacle: &Obstacle) -> ObstacleShape {
+    let points = &obstacle.points[..obstacle.point_count as usize];
+    match obstacle.kind {
+      0 => ObstacleShape::Circle {
+        center: points[0],
+        radius: obstacle.radius,
+      },
+      1 => ObstacleShape::Box {
+        min: points[0],
+        max: points[1],
+      },
+      2 => ObstacleShape::Capsule {
+        a: points[0],
+        b: points[1],
+        radius: obstacle.radius,
+      },
+      _ => ObstacleShape::Polygon { points: points.to_vec() },
+    }
+  }
+}
+
+fn segment_distance(p: [f32; 2], a: [f32; 2], b: [f32; 2]) -> f32 {
+  let pa = sub(p, a);
+  let ba = sub(b, a);
+  let h = (dot(pa, ba) / dot(ba, ba).max(1e-6)).clamp(0.0, 1.0);
+  length(sub(pa, scale(ba, h)))
+}
+
+/// Distance to the closest edge, negated when an even-odd crossing test puts `p` inside
+fn polygon_sdf(p: [f32; 2], points: &[[f32; 2]]) -> f32 {
+  let mut d = dot(sub(p, points[0]), sub(p, points[0]));
+  let mut inside_sign = 1.0;
+
+  let mut j = points.len() - 1;
+  for i in 0..points.len() {
+    let e = sub(points[j], points[i]);
+    let w = sub(p, points[i]);
+    let b = sub(w, scale(e, (dot(w, e) / dot(e, e).max(1e-6)).clamp(0.0, 1.0)));
+    d = d.min(dot(b, b));
+
+    let crossing = [p[1] >= points[i][1], p[1] < points[j][1], e[0] * w[1] > e[1] * w[0]];
+    if crossing.iter().all(|&c| c) || crossing.iter().all(|&c| !c) {
+      inside_sign = -inside_sign;
+    }
+    j = i;
+  }
+
+  inside_sign * d.sqrt()
+}
+
+fn add(a: [f32; 2], b: [f32; 2]) -> [f32; 2] {
+  [a[0] + b[0], a[1] + b[1]]
+}
+
+fn sub(a: [f32; 2], b: [f32; 2]) -> [f32; 2] {
+  [a[0] - b[0], a[1] - b[1]]
+}
+
+fn scale(v: [f32; 2], s: f32) -> [f32; 2] {
+  [v[0] * s, v[1] * s]
+}
+
+fn dot(a: [f32; 2], b: [f32; 2]) -> f32 {
+  a[0] * b[0] + a[1] * b[1]
+}
+
+fn length(v: [f32; 2]) -> f32 {
+  dot(v, v).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const EPSILON: f32 = 1e-4;
+
+  fn assert_sdf(shape: ObstacleShape, cases: &[([f32; 2], f32)]) {
+    let obstacle = Obstacle::from(&shape);
+    for &(p, expected) in cases {
+      let d = obstacle.sdf(p);
+      assert!((d - expected).abs() < EPSILON, "{shape:?} at {p:?}: {d}, expected {expected}");
+    }
+  }
+
+  #[test]
+  fn circle_sdf() {
+    let circle = ObstacleShape::Circle {
+      center: [10.0, 10.0],
+      radius: 5.0,
+    };
+    assert_sdf(circle, &[([10.0, 10.0], -5.0), ([15.0, 10.0], 0.0), ([10.0, 25.0], 10.0)]);
+  }
+
+  #[test]
+  fn box_sdf() {
+    let rect = ObstacleShape::Box {
+      min: [0.0, 0.0],
+      max: [20.0, 10.0],
+    };
+    // center, closest edge inside, straight out of an edge, diagonally out of a corner
+    assert_sdf(rect, &[([10.0, 5.0], -5.0), ([2.0, 5.0], -2.0), ([10.0, 13.0], 3.0), ([23.0, 14.0], 5.0)]);
+  }
+
+  #[test]
+  fn capsule_sdf() {
+    let capsule = ObstacleShape::Capsule {
+      a: [0.0, 0.0],
+      b: [10.0, 0.0],
+      radius: 2.0,
+    };
+    // beside the segment and past either end
+    assert_sdf(capsule, &[([5.0, 0.0], -2.0), ([5.0, 5.0], 3.0), ([-3.0, 4.0], 3.0), ([13.0, 0.0], 1.0)]);
+  }
+
+  #[test]
+  fn polygon_sdf_is_negative_inside() {
+    // concave L shape, the notch at the top right is outside
+    let l_shape = ObstacleShape::Polygon {
+      points: vec![[0.0, 0.0], [10.0, 0.0], [10.0, 4.0], [4.0, 4.0], [4.0, 10.0], [0.0, 10.0]],
+    };
+    assert_sdf(
+      l_shape,
+      &[
+        ([2.0, 2.0], -2.0),
+        ([8.0, 2.0], -2.0),
+        ([7.0, 7.0], 3.0),
+        ([2.0, 12.0], 2.0),
+        ([10.0, 2.0], 0.0),
+      ],
+    );
+  }
+
+  #[test]
+  fn shapes_survive_the_gpu_layout() {
+    let shapes = [
+      ObstacleShape::Circle {
+        center: [1.0, 2.0],
+        radius: 3.0,
+      },
+      ObstacleShape::Box {
+        min: [0.0, 0.0],
+        max: [4.0, 5.0],
+      },
+      ObstacleShape::Capsule {
+        a: [1.0, 1.0],
+        b: [6.0, 2.0],
+        radius: 0.5,
+      },
+      ObstacleShape::Polygon {
+        points: vec![[0.0, 0.0], [3.0, 0.0], [0.0, 3.0]],
+      },
+    ];
+
+    for shape in shapes {
+      assert_eq!(ObstacleShape::from(&Obstacle::from(&shape)), shape);
+    }
+  }
+
+  #[test]
+  fn polygon_keeps_at_most_max_points() {
+    let points: Vec<[f32; 2]> = (0..MAX_POLYGON_POINTS + 4).map(|i| [i as f32, (i % 2) as f32]).collect();
+    let obstacle = Obstacle::from(&ObstacleShape::Polygon { points: points.clone() });
+
+    assert_eq!(obstacle.point_count as usize, MAX_POLYGON_POINTS);
+    assert_eq!(obstacle.points, points[..MAX_POLYGON_POINTS]);
+  }
+}
@@ -1,14 +1,175 @@
 use bytemuck::{Pod, Zeroable};
+use serde::{Deserialize, Serialize};
 
 #[repr(C)]
 #[derive(Clone, Copy, Pod, Zeroable)]
 pub struct Params {
   pub dt: f32,
   pub window_count: u32,
+  pub cell_size: f32,
+  /// live particles, the buffers may be larger
+  pub particle_count: u32,
+  pub rules: BoidRules,
+  pub interaction: Interaction,
+  pub obstacle_count: u32,
+  /// `BoundaryMode` as u32
+  pub boundary: u32,
+  /// steps run so far, seeds the respawn positions of `BoundaryMode::Open`
+  pub step_index: u32,
+  /// blend radius of the smooth union of windows, 0 for a sharp union
+  pub smoothing: f32,
+  /// rectangle `BoundaryMode::Wrap` wraps around
+  pub world_min: [f32; 2],
+  pub world_max: [f32; 2],
 }
 
 impl Params {
   pub fn new() -> Params {
-    Params { dt: 0.0, window_count: 0 }
+    Params::with_rules(0.0, 0, 0, BoidRules::default())
+  }
+
+  pub fn with_rules(dt: f32, window_count: u32, particle_count: u32, rules: BoidRules) -> Params {
+    Params {
+      dt,
+      window_count,
+      cell_size: rules.max_radius(),
+      particle_count,
+      rules,
+      interaction: Interaction::none(),
+      obstacle_count: 0,
+      boundary: BoundaryMode::Soft as u32,
+      step_index: 0,
+      smoothing: 0.0,
+      world_min: [0.0, 0.0],
+      world_max: [0.0, 0.0],
+    }
+  }
+}
+
+/// What happens to particles at the edge of the simulated area
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BoundaryMode {
+  /// particles outside every window are pulled back towards the closest window edge
+  #[default]
+  Soft = 0,
+  /// particles leaving the world rectangle come back in on the opposite side
+  Wrap = 1,
+  /// particles reflect off the edges of the window they are in
+  Bounce = 2,
+  /// particles leaving every window are respawned inside a random window
+  Open = 3,
+}
+
+/// Force applied around a desktop-space point, mirrored by `Interaction` in params.wgsl
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Pod, Zeroable)]
+pub struct Interaction {
+  pub pos: [f32; 2],
+  /// `InteractionMode` as u32
+  pub mode: u32,
+  pub radius: f32,
+  pub strength: f32,
+  _padding: [f32; 3],
+}
+
+impl Interaction {
+  pub fn none() -> Interaction {
+    Interaction::new([0.0, 0.0], InteractionMode::None, 0.0, 0.0)
+  }
+
+  pub fn new(pos: [f32; 2], mode: InteractionMode, radius: f32, strength: f32) -> Interaction {
+    Interaction {
+      pos,
+      mode: mode as u32,
+      radius,
+      strength,
+      _padding: [0.0; 3],
+    }
+  }
+}
+
+/// What a mouse button does to particles near the cursor
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InteractionMode {
+  None = 0,
+  Attract = 1,
+  Repel = 2,
+  /// swirls particles counter-clockwise around the cursor
+  Vortex = 3,
+}
+
+/// Strengths and radii of the forces applied in move.wgsl, mirrored by `BoidRules` there
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Pod, Zeroable, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BoidRules {
+  pub max_speed: f32,
+  pub outside_strength: f32,
+  pub accel_strength: f32,
+
+  pub alignment_strength: f32,
+  pub alignment_radius: f32,
+
+  pub cohesion_far_strength: f32,
+  pub cohesion_far_radius: f32,
+  pub cohesion_close_strength: f32,
+  pub cohesion_close_radius: f32,
+
+  pub separation_strength: f32,
+  pub separation_radius: f32,
+
+  pub xenophobia_strength: f32,
+  pub xenophobia_start_radius: f32,
+  pub xenophobia_end_radius: f32,
+
+  /// cosine of the largest heading difference at which a faster neighbor passes its color on
+  pub contagion_min_cos: f32,
+
+  pub obstacle_strength: f32,
+  /// distance from an obstacle at which particles start steering away
+  pub obstacle_distance: f32,
+  #[serde(skip)]
+  _padding: [f32; 3],
+}
+
+impl BoidRules {
+  /// Largest distance at which any force reacts to a neighbor, used as the grid cell size
+  pub fn max_radius(&self) -> f32 {
+    [
+      self.alignment_radius,
+      self.cohesion_far_radius,
+      self.cohesion_close_radius,
+      self.separation_radius,
+      self.xenophobia_end_radius,
+    ]
+    .into_iter()
+    .fold(1.0, f32::max)
+  }
+}
+
+impl Default for BoidRules {
+  fn default() -> BoidRules {
+    BoidRules {
+      max_speed: 50.0,
+      outside_strength: 10.0,
+      accel_strength: 0.1,
+      alignment_strength: 10.0,
+      alignment_radius: 200.0,
+      cohesion_far_strength: 1.0,
+      cohesion_far_radius: 150.0,
+      cohesion_close_strength: 20.0,
+      cohesion_close_radius: 100.0,
+      separation_strength: 30.0,
+      separation_radius: 15.0,
+      xenophobia_strength: 5.0,
+      xenophobia_start_radius: 200.0,
+      xenophobia_end_radius: 250.0,
+      contagion_min_cos: 0.866,
+      obstacle_strength: 200.0,
+      obstacle_distance: 40.0,
+      _padding: [0.0; 3],
+    }
   }
 }
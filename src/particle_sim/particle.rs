@@ -1,32 +1,105 @@
 use bytemuck::{Pod, Zeroable};
+use rand::Rng;
+
+use crate::config::{SpawnConfig, SpeciesConfig};
 
 #[repr(C)]
-#[derive(Clone, Copy, Pod, Zeroable)]
+#[derive(Clone, Copy, Debug, PartialEq, Pod, Zeroable)]
 pub struct Particle {
-  pos: [f32; 2],
-  vel: [f32; 2],
-  color: [f32; 4],
+  pub pos: [f32; 2],
+  pub vel: [f32; 2],
+  pub color: [f32; 4],
+  /// index into the species table
+  pub species: u32,
+  _padding: [u32; 3],
 }
 
 impl Particle {
-  pub fn random() -> Particle {
-    let pos_range = 0.0..1024.0;
-    let vel_range = -10.0..10.0;
+  pub fn new(pos: [f32; 2], vel: [f32; 2], color: [f32; 4], species: u32) -> Particle {
+    Particle {
+      pos,
+      vel,
+      color,
+      species,
+      _padding: [0; 3],
+    }
+  }
 
-    let x = rand::random_range(pos_range.clone());
-    let y = rand::random_range(pos_range.clone());
+  pub fn random(spawn: &SpawnConfig, species: &[SpeciesConfig], rng: &mut impl Rng) -> Particle {
+    let vel_range = -spawn.max_velocity..spawn.max_velocity;
 
-    let vel_x = rand::random_range(vel_range.clone());
-    let vel_y = rand::random_range(vel_range.clone());
+    let x = rng.random_range(spawn.pos_min[0]..spawn.pos_max[0]);
+    let y = rng.random_range(spawn.pos_min[1]..spawn.pos_max[1]);
 
-    let r = rand::random::<f32>();
-    let g = rand::random::<f32>();
-    let b = rand::random::<f32>();
+    let vel_x = rng.random_range(vel_range.clone());
+    let vel_y = rng.random_range(vel_range.clone());
 
-    Particle {
-      pos: [x, y],
-      vel: [vel_x, vel_y],
-      color: [r, g, b, 1.0],
+    let r = rng.random::<f32>();
+    let g = rng.random::<f32>();
+    let b = rng.random::<f32>();
+
+    let index = Particle::random_species(species, rng);
+    let color = species.get(index).and_then(|s| s.color).unwrap_or([r, g, b, 1.0]);
+
+    Particle::new([x, y], [vel_x, vel_y], color, index as u32)
+  }
+
+  /// Picks a species weighted by its share, a single species doesn't draw from `rng`
+  fn random_species(species: &[SpeciesConfig], rng: &mut impl Rng) -> usize {
+    if species.len() <= 1 {
+      return 0;
+    }
+
+    let total: f32 = species.iter().map(|s| s.share).sum();
+    let mut pick = rng.random_range(0.0..total);
+    for (index, s) in species.iter().enumerate() {
+      if pick < s.share {
+        return index;
+      }
+      pick -= s.share;
+    }
+
+    species.len() - 1
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use rand::{SeedableRng, rngs::StdRng};
+
+  fn species(shares: &[f32]) -> Vec<SpeciesConfig> {
+    shares
+      .iter()
+      .map(|&share| SpeciesConfig {
+        share,
+        ..SpeciesConfig::default()
+      })
+      .collect()
+  }
+
+  #[test]
+  fn species_are_picked_by_share() {
+    let species = species(&[1.0, 3.0, 0.0001]);
+    let mut rng = StdRng::seed_from_u64(1);
+
+    let mut counts = [0; 3];
+    for _ in 0..40_000 {
+      counts[Particle::random_species(&species, &mut rng)] += 1;
     }
+
+    assert!((9_000..11_000).contains(&counts[0]), "{counts:?}");
+    assert!((29_000..31_000).contains(&counts[1]), "{counts:?}");
+    assert!(counts[2] < 50, "{counts:?}");
+  }
+
+  #[test]
+  fn single_species_leaves_rng_untouched() {
+    let mut rng = StdRng::seed_from_u64(2);
+    let mut untouched = rng.clone();
+
+    assert_eq!(Particle::random_species(&species(&[5.0]), &mut rng), 0);
+    assert_eq!(Particle::random_species(&[], &mut rng), 0);
+    assert_eq!(rng.random::<u64>(), untouched.random::<u64>());
   }
 }
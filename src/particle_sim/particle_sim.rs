@@ -1,58 +1,232 @@
 use crate::{
-  app::{gpu_wrapper::GpuWrapper, window_wrapper::WindowWrapper},
-  particle_sim::{compute_pass::ComputePass, render_pass::RenderPass, window::Window},
+  app::gpu_wrapper::GpuWrapper,
+  config::SimConfig,
+  particle_sim::{
+    compute_pass::ComputePass,
+    obstacle::{Obstacle, ObstacleShape},
+    params::{BoidRules, Interaction, Params},
+    particle::Particle,
+    readback::{Readback, ReadbackError},
+    render_pass::{ColorMode, RenderPass, RenderTarget},
+    view::WindowView,
+    window::Window,
+  },
 };
+use tracing::error;
 use wgpu::{
-  Buffer, BufferUsages, CommandEncoder, Device, Queue, TextureView,
+  Buffer, BufferDescriptor, BufferUsages, CommandEncoder, ComputePassTimestampWrites, Device, Queue, RenderPassTimestampWrites, TextureFormat,
+  TextureView,
   util::{BufferInitDescriptor, DeviceExt},
 };
 
+/// windows the compute windows buffer has room for at first, it doubles whenever more are open
+const INITIAL_WINDOW_CAPACITY: usize = 8;
+
 pub struct ParticleSim {
-  render_window_buffer: Buffer,
+  window_capacity: usize,
+  /// whether more windows are open than the device can bind, so the error is logged once
+  window_limit_exceeded: bool,
+  max_obstacles: usize,
+  /// as uploaded to the obstacle buffer
+  obstacles: Vec<Obstacle>,
   compute_windows_buffer: Buffer,
+  obstacle_buffer: Buffer,
   compute: ComputePass,
   render: RenderPass,
+  color_mode: ColorMode,
 }
 
 impl ParticleSim {
-  pub fn init(gpu: &GpuWrapper, window: &WindowWrapper) -> ParticleSim {
-    let compute_windows_buffer = ParticleSim::init_compute_windows_buffer(&gpu.device);
-    let compute = ComputePass::init(gpu, &compute_windows_buffer);
+  pub fn init(gpu: &GpuWrapper, format: TextureFormat, config: &SimConfig) -> ParticleSim {
+    ParticleSim::init_with(gpu, format, config, |windows_buffer, obstacle_buffer| {
+      ComputePass::init(gpu, windows_buffer, obstacle_buffer, config)
+    })
+  }
+
+  /// Like `init`, but continues from `particles`, e.g. a snapshot taken before the GPU device was lost.
+  /// `spawned` is the `spawned` count taken with them, so added particles continue the spawn sequence
+  pub fn init_with_particles(gpu: &GpuWrapper, format: TextureFormat, config: &SimConfig, particles: Vec<Particle>, spawned: u64) -> ParticleSim {
+    ParticleSim::init_with(gpu, format, config, |windows_buffer, obstacle_buffer| {
+      ComputePass::init_with_particles(gpu, windows_buffer, obstacle_buffer, config, particles, spawned)
+    })
+  }
+
+  fn init_with(
+    gpu: &GpuWrapper,
+    format: TextureFormat,
+    config: &SimConfig,
+    init_compute: impl FnOnce(&Buffer, &Buffer) -> ComputePass,
+  ) -> ParticleSim {
+    let window_capacity = INITIAL_WINDOW_CAPACITY;
+    let compute_windows_buffer = ParticleSim::init_compute_windows_buffer(&gpu.device, window_capacity);
+    let max_obstacles = config.max_obstacles as usize;
+    let obstacle_buffer = ParticleSim::init_obstacle_buffer(&gpu.device, max_obstacles);
+    let compute = init_compute(&compute_windows_buffer, &obstacle_buffer);
 
-    let render_window_buffer = ParticleSim::init_window_buffer(&gpu.device);
     let (particle_buffer, particle_count) = compute.get_particle_buffer();
-    let render = RenderPass::init(gpu, window, &render_window_buffer, particle_buffer.clone(), particle_count);
+    let render = RenderPass::init(
+      gpu,
+      RenderTarget::new(format),
+      particle_buffer.clone(),
+      particle_count,
+      &obstacle_buffer,
+      config,
+    );
 
-    ParticleSim {
-      render_window_buffer,
+    let mut sim = ParticleSim {
+      window_capacity,
+      window_limit_exceeded: false,
+      max_obstacles,
+      obstacles: Vec::new(),
       compute_windows_buffer,
+      obstacle_buffer,
       compute,
       render,
+      color_mode: ColorMode::default(),
+    };
+    sim.set_obstacles(&gpu.queue, &config.obstacles);
+    sim
+  }
+
+  /// Draws the part of the simulation `window_view` shows into `view`, `target` describes the view.
+  /// `timestamp_writes` measure the draw if given
+  pub fn render(
+    &mut self,
+    encoder: &mut CommandEncoder,
+    gpu: &GpuWrapper,
+    window_view: &WindowView,
+    view: &TextureView,
+    target: RenderTarget,
+    timestamp_writes: Option<RenderPassTimestampWrites>,
+  ) {
+    self.render.run(encoder, &gpu.device, window_view, view, target, timestamp_writes);
+  }
+
+  /// Runs one simulation step of `dt` simulated seconds, `timestamp_writes` measure it if given
+  pub fn compute(
+    &mut self,
+    encoder: &mut CommandEncoder,
+    gpu: &GpuWrapper,
+    windows: Vec<Window>,
+    dt: f32,
+    timestamp_writes: Option<ComputePassTimestampWrites>,
+  ) {
+    let windows = self.update_compute_window_buffer(gpu, &windows);
+    self.compute.run(encoder, gpu, windows, dt, timestamp_writes);
+  }
+
+  /// Params the next step of `dt` runs with over `windows`, e.g. to store them in a snapshot
+  pub fn params(&self, windows: &[Window], dt: f32) -> Params {
+    self.compute.params(windows, dt)
+  }
+
+  /// Continues the step count of a snapshot
+  pub fn set_step_index(&mut self, step_index: u32) {
+    self.compute.set_step_index(step_index);
+  }
+
+  /// Respawns every particle from `config`
+  pub fn reset(&mut self, gpu: &GpuWrapper, config: &SimConfig) {
+    self.compute.reset(&gpu.queue, config);
+  }
+
+  /// Particles spawned since the seed was applied, stored with snapshots and recovery copies
+  pub fn spawned(&self) -> u64 {
+    self.compute.spawned()
+  }
+
+  /// Continues the spawn sequence of `config`'s seed after the first `spawned` particles
+  pub fn restore_rng(&mut self, config: &SimConfig, spawned: u64) {
+    self.compute.restore_rng(config, spawned);
+  }
+
+  /// Starts copying the current particle state back to the CPU without waiting for it
+  pub fn readback(&self, gpu: &GpuWrapper) -> Readback<Particle> {
+    self.compute.readback(gpu)
+  }
+
+  /// Blocks until the current particle state is copied back to the CPU
+  pub fn read_particles(&self, gpu: &GpuWrapper) -> Result<Vec<Particle>, ReadbackError> {
+    self.compute.read_particles(gpu)
+  }
+
+  pub fn particle_count(&self) -> u32 {
+    self.compute.get_particle_buffer().1
+  }
+
+  /// Grows or shrinks the population while keeping the state of the particles that stay, returns the new count
+  pub fn set_particle_count(&mut self, gpu: &GpuWrapper, count: u32) -> u32 {
+    let reallocated = self.compute.set_particle_count(gpu, count);
+    let (particle_buffer, count) = self.compute.get_particle_buffer();
+
+    // compute dispatch and draw instance count have to agree, or the draw reads stale slots
+    if reallocated {
+      self.render.set_particle_buffer(&gpu.device, particle_buffer, count);
+    } else {
+      self.render.set_particle_count(count);
     }
+
+    count
   }
 
-  pub fn render(&mut self, encoder: &mut CommandEncoder, gpu: &GpuWrapper, window: &WindowWrapper, view: &TextureView) {
-    self.update_render_window_buffer(&gpu.queue, window);
-    self.render.run(encoder, view);
+  /// Replaces every particle, the count has to stay the same
+  pub fn set_particles(&mut self, gpu: &GpuWrapper, particles: &[Particle]) {
+    self.compute.set_particles(&gpu.queue, particles);
   }
 
-  pub fn compute(&mut self, encoder: &mut CommandEncoder, gpu: &GpuWrapper, windows: Vec<&WindowWrapper>) {
-    let count = windows.len() as u32;
-    self.update_compute_window_buffer(&gpu.queue, windows);
-    self.compute.run(encoder, gpu, count);
+  pub fn color_mode(&self) -> ColorMode {
+    self.color_mode
   }
 
-  fn init_window_buffer(device: &Device) -> Buffer {
-    device.create_buffer_init(&BufferInitDescriptor {
-      label: Some("Window Buffer"),
-      contents: bytemuck::bytes_of(&Window::empty()),
-      usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
-    })
+  pub fn set_color_mode(&mut self, gpu: &GpuWrapper, color_mode: ColorMode) {
+    self.color_mode = color_mode;
+    self.render.set_color_mode(&gpu.queue, color_mode, self.rules().max_speed);
   }
 
-  fn init_compute_windows_buffer(device: &Device) -> Buffer {
-    const MAX: usize = 32;
-    let data = vec![Window::empty(); MAX];
+  /// Switches to the next color mode and returns it
+  pub fn cycle_color_mode(&mut self, gpu: &GpuWrapper) -> ColorMode {
+    self.set_color_mode(gpu, self.color_mode.next());
+    self.color_mode
+  }
+
+  pub fn rules(&self) -> &BoidRules {
+    self.compute.rules()
+  }
+
+  /// Replaces the boid rules, they take effect from the next compute step
+  pub fn set_rules(&mut self, queue: &Queue, rules: BoidRules) {
+    self.compute.set_rules(queue, rules);
+  }
+
+  /// Sets the force applied around the cursor, `Interaction::none()` turns it off
+  pub fn set_interaction(&mut self, interaction: Interaction) {
+    self.compute.set_interaction(interaction);
+  }
+
+  /// Uploads the obstacles particles steer around and that are drawn below them, anything past `sim.max_obstacles` is dropped
+  pub fn set_obstacles(&mut self, queue: &Queue, shapes: &[ObstacleShape]) {
+    let obstacles: Vec<Obstacle> = shapes.iter().take(self.max_obstacles).map(Obstacle::from).collect();
+    queue.write_buffer(&self.obstacle_buffer, 0, bytemuck::cast_slice(&obstacles));
+
+    let count = obstacles.len() as u32;
+    self.compute.set_obstacle_count(count);
+    self.render.set_obstacle_count(count);
+    self.obstacles = obstacles;
+  }
+
+  pub fn obstacles(&self) -> &[Obstacle] {
+    &self.obstacles
+  }
+
+  /// Most windows a single storage binding can hold on `device`
+  fn max_windows(device: &Device) -> usize {
+    let limits = device.limits();
+    let max_bytes = (limits.max_storage_buffer_binding_size as u64).min(limits.max_buffer_size);
+    (max_bytes / size_of::<Window>() as u64) as usize
+  }
+
+  fn init_compute_windows_buffer(device: &Device, capacity: usize) -> Buffer {
+    let data = vec![Window::empty(); capacity];
 
     device.create_buffer_init(&BufferInitDescriptor {
       label: Some("All Windows Buffer"),
@@ -61,25 +235,94 @@ impl ParticleSim {
     })
   }
 
-  fn update_render_window_buffer(&mut self, queue: &Queue, window: &WindowWrapper) {
-    let Ok(top_left) = window.window.inner_position() else {
-      return;
+  fn init_obstacle_buffer(device: &Device, max_obstacles: usize) -> Buffer {
+    device.create_buffer(&BufferDescriptor {
+      label: Some("Obstacle buffer"),
+      size: (max_obstacles.max(1) * size_of::<Obstacle>()) as u64,
+      usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
+      mapped_at_creation: false,
+    })
+  }
+
+  /// Uploads `windows`, growing the buffer when they don't fit. Returns the uploaded windows,
+  /// which are all of them unless the device limit is hit
+  fn update_compute_window_buffer<'a>(&mut self, gpu: &GpuWrapper, windows: &'a [Window]) -> &'a [Window] {
+    let max_windows = ParticleSim::max_windows(&gpu.device);
+    let exceeded = windows.len() > max_windows;
+    if exceeded && !self.window_limit_exceeded {
+      error!(
+        "{} windows are open but the device can only bind {max_windows}, the simulation ignores the rest",
+        windows.len()
+      );
+    }
+    self.window_limit_exceeded = exceeded;
+    let windows = &windows[..windows.len().min(max_windows)];
+
+    if windows.len() > self.window_capacity {
+      self.window_capacity = windows.len().next_power_of_two().min(max_windows);
+      self.compute_windows_buffer = ParticleSim::init_compute_windows_buffer(&gpu.device, self.window_capacity);
+      self.compute.set_window_buffer(&gpu.device, &self.compute_windows_buffer);
+    }
+
+    gpu.queue.write_buffer(&self.compute_windows_buffer, 0, bytemuck::cast_slice(windows));
+    windows
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::particle_sim::cpu::tests::test_gpu;
+
+  /// Particles told apart by their position
+  fn numbered(count: u32) -> Vec<Particle> {
+    (0..count).map(|i| Particle::new([i as f32, 0.0], [0.0, 0.0], [1.0; 4], 0)).collect()
+  }
+
+  #[test]
+  fn resizing_keeps_particles_and_draw_count() {
+    let gpu = test_gpu();
+    let config = SimConfig {
+      particle_count: 64,
+      ..SimConfig::default()
     };
-    let size = window.window.inner_size().cast::<f32>();
-    let data: [[f32; 2]; 2] = [
-      [top_left.x as f32, top_left.y as f32],
-      [top_left.x as f32 + size.width, top_left.y as f32 + size.height],
-    ];
-    queue.write_buffer(&self.render_window_buffer, 0, bytemuck::bytes_of(&data));
-  }
-
-  fn update_compute_window_buffer(&mut self, queue: &Queue, windows: Vec<&WindowWrapper>) {
-    const MAX: usize = 32;
-    if windows.len() > MAX {
-      return;
+    let mut sim = ParticleSim::init_with_particles(&gpu, TextureFormat::Rgba8UnormSrgb, &config, numbered(64), 0);
+
+    // past the initial headroom, so the buffers are reallocated
+    for count in [100, 4096, 10, 20] {
+      let kept = sim.particle_count().min(count) as usize;
+      let before = sim.read_particles(&gpu).unwrap();
+
+      assert_eq!(sim.set_particle_count(&gpu, count), count);
+      assert_eq!(sim.particle_count(), count);
+      assert_eq!(sim.render.particle_count(), count, "draw count out of sync");
+
+      let after = sim.read_particles(&gpu).unwrap();
+      assert_eq!(after.len(), count as usize);
+      assert_eq!(after[..kept], before[..kept], "resizing to {count} changed kept particles");
+    }
+  }
+  #[test]
+  fn rebuilt_sim_continues_the_spawn_sequence() {
+    let gpu = test_gpu();
+    let config = SimConfig {
+      particle_count: 16,
+      seed: Some(7),
+      ..SimConfig::default()
     };
+    let mut sim = ParticleSim::init(&gpu, TextureFormat::Rgba8UnormSrgb, &config);
+    sim.set_particle_count(&gpu, 24);
+    let particles = sim.read_particles(&gpu).unwrap();
+    assert_eq!(sim.spawned(), 24);
+
+    let mut rebuilt = ParticleSim::init_with_particles(&gpu, TextureFormat::Rgba8UnormSrgb, &config, particles, sim.spawned());
+    sim.set_particle_count(&gpu, 40);
+    rebuilt.set_particle_count(&gpu, 40);
+    assert_eq!(rebuilt.read_particles(&gpu).unwrap(), sim.read_particles(&gpu).unwrap());
 
-    let windows_data: Vec<Window> = windows.iter().map(|&wrapper| Window::from(wrapper)).collect();
-    queue.write_buffer(&self.compute_windows_buffer, 0, bytemuck::cast_slice(&windows_data));
+    // shrinking doesn't rewind the sequence, the regrown particles are new ones
+    sim.set_particle_count(&gpu, 8);
+    sim.set_particle_count(&gpu, 16);
+    assert_ne!(sim.read_particles(&gpu).unwrap()[8..], rebuilt.read_particles(&gpu).unwrap()[8..16]);
   }
 }
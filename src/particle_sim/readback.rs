@@ -0,0 +1,112 @@
+use crate::app::gpu_wrapper::GpuWrapper;
+use bytemuck::Pod;
+use std::{
+  marker::PhantomData,
+  sync::mpsc::{self, Receiver, TryRecvError},
+};
+use wgpu::{
+  Buffer, BufferAsyncError, BufferDescriptor, BufferUsages, CommandEncoder, CommandEncoderDescriptor, Device, MapMode, PollError, PollType,
+};
+
+/// Copy of a GPU buffer on its way to the CPU. The copy is submitted on creation and the data
+/// can be taken without blocking once the GPU is done, or waited for.
+///
+/// Not a `Future` on purpose: the map callback only runs while the device is polled,
+/// so awaiting it would come down to spinning on `try_take`
+pub struct Readback<T> {
+  device: Device,
+  /// `None` for an empty copy, wgpu can't map an empty slice
+  staging_buffer: Option<Buffer>,
+  receiver: Receiver<Result<(), BufferAsyncError>>,
+  _data: PhantomData<fn() -> T>,
+}
+
+impl<T: Pod> Readback<T> {
+  /// Starts copying the first `len` elements of `buffer`, which needs `BufferUsages::COPY_SRC`
+  pub fn new(gpu: &GpuWrapper, buffer: &Buffer, len: usize) -> Readback<T> {
+    let size = (len * size_of::<T>()) as u64;
+    Readback::with_copy(gpu, size, |encoder, staging_buffer| {
+      encoder.copy_buffer_to_buffer(buffer, 0, staging_buffer, 0, size);
+    })
+  }
+
+  /// Starts a copy of `size` bytes that `copy` records into the staging buffer, e.g. from a texture.
+  /// `size` has to be a multiple of the size of `T`
+  pub fn with_copy(gpu: &GpuWrapper, size: u64, copy: impl FnOnce(&mut CommandEncoder, &Buffer)) -> Readback<T> {
+    let (sender, receiver) = mpsc::channel();
+    if size == 0 {
+      let _ = sender.send(Ok(()));
+      return Readback {
+        device: gpu.device.clone(),
+        staging_buffer: None,
+        receiver,
+        _data: PhantomData,
+      };
+    }
+
+    let staging_buffer = gpu.device.create_buffer(&BufferDescriptor {
+      label: Some("Readback staging buffer"),
+      size,
+      usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+      mapped_at_creation: false,
+    });
+
+    let mut command_encoder = gpu.device.create_command_encoder(&CommandEncoderDescriptor::default());
+    copy(&mut command_encoder, &staging_buffer);
+    gpu.queue.submit(Some(command_encoder.finish()));
+
+    staging_buffer.slice(..).map_async(MapMode::Read, move |result| {
+      let _ = sender.send(result);
+    });
+
+    Readback {
+      device: gpu.device.clone(),
+      staging_buffer: Some(staging_buffer),
+      receiver,
+      _data: PhantomData,
+    }
+  }
+
+  /// Returns the data if the copy has finished, without blocking
+  pub fn try_take(&mut self) -> Option<Result<Vec<T>, ReadbackError>> {
+    if let Err(e) = self.device.poll(PollType::Poll) {
+      return Some(Err(e.into()));
+    }
+
+    match self.receiver.try_recv() {
+      Ok(Ok(())) => Some(Ok(self.take_mapped())),
+      Ok(Err(e)) => Some(Err(e.into())),
+      Err(TryRecvError::Empty) => None,
+      Err(TryRecvError::Disconnected) => Some(Err(ReadbackError::MapCallbackDropped)),
+    }
+  }
+
+  /// Blocks until the copy has finished
+  pub fn wait(mut self) -> Result<Vec<T>, ReadbackError> {
+    self.device.poll(PollType::wait_indefinitely())?;
+    self.try_take().unwrap_or(Err(ReadbackError::MapCallbackDropped))
+  }
+
+  fn take_mapped(&self) -> Vec<T> {
+    let Some(staging_buffer) = &self.staging_buffer else {
+      return Vec::new();
+    };
+
+    let data = bytemuck::cast_slice(&staging_buffer.slice(..).get_mapped_range()).to_vec();
+    staging_buffer.unmap();
+    data
+  }
+}
+
+#[allow(clippy::enum_variant_names)]
+#[derive(thiserror::Error, Debug)]
+pub enum ReadbackError {
+  #[error("Device poll error: {0}")]
+  PollError(#[from] PollError),
+
+  #[error("Buffer map error: {0}")]
+  BufferMapError(#[from] BufferAsyncError),
+
+  #[error("Buffer map callback was dropped before completing")]
+  MapCallbackDropped,
+}
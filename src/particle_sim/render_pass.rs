@@ -1,26 +1,100 @@
-use crate::app::{gpu_wrapper::GpuWrapper, window_wrapper::WindowWrapper};
+use crate::{app::gpu_wrapper::GpuWrapper, config::SimConfig, particle_sim::view::WindowView};
+use std::collections::HashMap;
+use tracing::debug;
 use wgpu::{
-  BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingType, Buffer,
-  BufferBindingType, BufferUsages, Color, CommandEncoder, Device, FragmentState, LoadOp, MultisampleState, Operations, PipelineLayoutDescriptor,
-  PrimitiveState, RenderPassColorAttachment, RenderPassDescriptor, RenderPipeline, RenderPipelineDescriptor, ShaderStages, StoreOp, TextureView,
+  BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingType, BlendState, Buffer,
+  BufferBindingType, BufferUsages, Color, ColorTargetState, ColorWrites, CommandEncoder, Device, FragmentState, LoadOp, MultisampleState, Operations,
+  PipelineLayoutDescriptor, PrimitiveState, PrimitiveTopology, Queue, RenderPassColorAttachment, RenderPassDescriptor, RenderPassTimestampWrites,
+  RenderPipeline, RenderPipelineDescriptor, ShaderModuleDescriptor, ShaderSource, ShaderStages, StoreOp, TextureFormat, TextureView,
   VertexBufferLayout, VertexState, VertexStepMode, include_wgsl,
   util::{BufferInitDescriptor, DeviceExt},
   vertex_attr_array,
 };
 
+/// How particles are colored when drawn
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ColorMode {
+  /// color assigned by the simulation
+  #[default]
+  Particle,
+  /// hue follows the direction of travel
+  Heading,
+  /// blue for slow through red for particles at max speed
+  Speed,
+}
+
+impl ColorMode {
+  pub fn next(self) -> ColorMode {
+    match self {
+      ColorMode::Particle => ColorMode::Heading,
+      ColorMode::Heading => ColorMode::Speed,
+      ColorMode::Speed => ColorMode::Particle,
+    }
+  }
+}
+
+/// Color attachment a pipeline is built for, every window may have its own
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct RenderTarget {
+  pub format: TextureFormat,
+  pub sample_count: u32,
+}
+
+impl RenderTarget {
+  /// Single sampled target of `format`
+  pub fn new(format: TextureFormat) -> RenderTarget {
+    RenderTarget { format, sample_count: 1 }
+  }
+}
+
+/// Uniform with draw settings, mirrors `RenderParams` in draw.wgsl
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct RenderParams {
+  color_mode: u32,
+  max_speed: f32,
+  _padding: [u32; 2],
+}
+
 pub struct RenderPass {
   vertex_buffer: Buffer,
-  pipeline: RenderPipeline,
+  render_params_buffer: Buffer,
+  pipelines: HashMap<RenderTarget, Pipelines>,
 
   particle_count: u32,
 
+  layout: BindGroupLayout,
   bind_group: BindGroup,
+
+  obstacle_layout: BindGroupLayout,
+  obstacle_bind_group: BindGroup,
+  view_layout: BindGroupLayout,
+  obstacle_count: u32,
+}
+
+/// Everything drawn into one kind of render target
+struct Pipelines {
+  particles: RenderPipeline,
+  obstacles: RenderPipeline,
 }
 
 impl RenderPass {
-  pub fn run(&mut self, encoder: &mut CommandEncoder, view: &TextureView) {
+  /// Draws the part of the simulation `window_view` shows into `view`, which has to match `target`.
+  /// Pipelines for a new target are built on first use, `timestamp_writes` measure the pass if given
+  pub fn run(
+    &mut self,
+    encoder: &mut CommandEncoder,
+    device: &Device,
+    window_view: &WindowView,
+    view: &TextureView,
+    target: RenderTarget,
+    timestamp_writes: Option<RenderPassTimestampWrites>,
+  ) {
+    self.prepare(device, target);
+    let pipelines = &self.pipelines[&target];
+
     let color_attachments = [Some(RenderPassColorAttachment {
-      view: &view,
+      view,
       depth_slice: None,
       resolve_target: None,
       ops: Operations {
@@ -33,40 +107,103 @@ impl RenderPass {
       label: Some("Render pass descriptor"),
       color_attachments: &color_attachments,
       depth_stencil_attachment: None,
-      timestamp_writes: None,
+      timestamp_writes,
       occlusion_query_set: None,
       multiview_mask: None,
     };
 
     let mut rpass = encoder.begin_render_pass(&render_pass_descriptor);
+    rpass.set_bind_group(1, window_view.bind_group(), &[]);
 
-    rpass.set_pipeline(&self.pipeline);
+    if self.obstacle_count > 0 {
+      rpass.set_pipeline(&pipelines.obstacles);
+      rpass.set_bind_group(0, &self.obstacle_bind_group, &[]);
+      rpass.draw(0..4, 0..self.obstacle_count);
+    }
+
+    rpass.set_pipeline(&pipelines.particles);
     rpass.set_bind_group(0, &self.bind_group, &[]);
     rpass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
     rpass.draw(0..3, 0..self.particle_count);
   }
 
-  pub fn init(gpu: &GpuWrapper, window: &WindowWrapper, window_buffer: &Buffer, particle_buffer: Buffer, particle_count: u32) -> RenderPass {
+  #[cfg(test)]
+  pub fn particle_count(&self) -> u32 {
+    self.particle_count
+  }
+
+  /// Draws `particle_count` particles from now on, the particle buffer stays the same
+  pub fn set_particle_count(&mut self, particle_count: u32) {
+    self.particle_count = particle_count;
+  }
+
+  /// Points the pass at a reallocated particle buffer
+  pub fn set_particle_buffer(&mut self, device: &Device, particle_buffer: &Buffer, particle_count: u32) {
+    self.bind_group = RenderPass::init_bind_group(device, &self.layout, particle_buffer, &self.render_params_buffer);
+    self.particle_count = particle_count;
+  }
+
+  /// Draws the first `obstacle_count` obstacles of the obstacle buffer from now on
+  pub fn set_obstacle_count(&mut self, obstacle_count: u32) {
+    self.obstacle_count = obstacle_count;
+  }
+
+  /// `max_speed` is the speed drawn as fully red in `ColorMode::Speed`
+  pub fn set_color_mode(&mut self, queue: &Queue, color_mode: ColorMode, max_speed: f32) {
+    let render_params = RenderParams::new(color_mode, max_speed);
+    queue.write_buffer(&self.render_params_buffer, 0, bytemuck::bytes_of(&render_params));
+  }
+
+  /// Builds pipelines for `target` right away, pipelines for other targets are built when first drawn to
+  pub fn init(
+    gpu: &GpuWrapper,
+    target: RenderTarget,
+    particle_buffer: Buffer,
+    particle_count: u32,
+    obstacle_buffer: &Buffer,
+    config: &SimConfig,
+  ) -> RenderPass {
     let device = &gpu.device;
-    let vertex_buffer = RenderPass::init_vertex_buffer(device);
+    let vertex_buffer = RenderPass::init_vertex_buffer(device, config.triangle_size);
+    let render_params_buffer = RenderPass::init_render_params_buffer(device, config.rules.max_speed);
 
     let layout = RenderPass::init_bind_group_layout(device);
-    let bind_group = RenderPass::init_bind_group(device, &layout, &particle_buffer, &window_buffer);
-    let pipeline = RenderPass::init_pipeline(device, &layout, window);
+    let bind_group = RenderPass::init_bind_group(device, &layout, &particle_buffer, &render_params_buffer);
+
+    let obstacle_layout = RenderPass::init_obstacle_bind_group_layout(device);
+    let obstacle_bind_group = RenderPass::init_obstacle_bind_group(device, &obstacle_layout, obstacle_buffer);
 
-    RenderPass {
+    let mut render = RenderPass {
       vertex_buffer,
-      pipeline,
+      render_params_buffer,
+      pipelines: HashMap::new(),
       particle_count,
+      layout,
       bind_group,
-    }
+      obstacle_layout,
+      obstacle_bind_group,
+      view_layout: WindowView::init_layout(device),
+      obstacle_count: 0,
+    };
+    render.prepare(device, target);
+    render
+  }
+
+  fn prepare(&mut self, device: &Device, target: RenderTarget) {
+    self.pipelines.entry(target).or_insert_with(|| {
+      debug!("Building render pipelines for {target:?}");
+      Pipelines {
+        particles: RenderPass::init_pipeline(device, &[&self.layout, &self.view_layout], target),
+        obstacles: RenderPass::init_obstacle_pipeline(device, &[&self.obstacle_layout, &self.view_layout], target),
+      }
+    });
   }
 
-  fn init_pipeline(device: &Device, bind_group_layout: &BindGroupLayout, window: &WindowWrapper) -> RenderPipeline {
+  fn init_pipeline(device: &Device, bind_group_layouts: &[&BindGroupLayout], target: RenderTarget) -> RenderPipeline {
     let shader = device.create_shader_module(include_wgsl!("shaders/draw.wgsl"));
     let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
       label: Some("Render pipeline layout"),
-      bind_group_layouts: &[bind_group_layout],
+      bind_group_layouts,
       immediate_size: 0,
     });
 
@@ -87,21 +224,67 @@ impl RenderPass {
         module: &shader,
         entry_point: Some("main_fs"),
         compilation_options: Default::default(),
-        targets: &[Some(window.surface_config.format.into())],
+        targets: &[Some(target.format.into())],
       }),
       primitive: PrimitiveState::default(),
       depth_stencil: None,
-      multisample: MultisampleState::default(),
+      multisample: MultisampleState {
+        count: target.sample_count,
+        ..Default::default()
+      },
+      multiview_mask: None,
+      cache: None,
+    })
+  }
+
+  fn init_obstacle_pipeline(device: &Device, bind_group_layouts: &[&BindGroupLayout], target: RenderTarget) -> RenderPipeline {
+    let shader = device.create_shader_module(ShaderModuleDescriptor {
+      label: Some("Obstacle shader"),
+      source: ShaderSource::Wgsl(concat!(include_str!("shaders/obstacle.wgsl"), include_str!("shaders/obstacles.wgsl")).into()),
+    });
+    let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+      label: Some("Obstacle pipeline layout"),
+      bind_group_layouts,
+      immediate_size: 0,
+    });
+
+    device.create_render_pipeline(&RenderPipelineDescriptor {
+      label: Some("Obstacle pipeline"),
+      layout: Some(&pipeline_layout),
+      vertex: VertexState {
+        module: &shader,
+        entry_point: Some("main_vs"),
+        compilation_options: Default::default(),
+        buffers: &[],
+      },
+      fragment: Some(FragmentState {
+        module: &shader,
+        entry_point: Some("main_fs"),
+        compilation_options: Default::default(),
+        targets: &[Some(ColorTargetState {
+          format: target.format,
+          blend: Some(BlendState::ALPHA_BLENDING),
+          write_mask: ColorWrites::ALL,
+        })],
+      }),
+      primitive: PrimitiveState {
+        topology: PrimitiveTopology::TriangleStrip,
+        ..Default::default()
+      },
+      depth_stencil: None,
+      multisample: MultisampleState {
+        count: target.sample_count,
+        ..Default::default()
+      },
       multiview_mask: None,
       cache: None,
     })
   }
 
-  fn init_vertex_buffer(device: &Device) -> Buffer {
-    let size = 3.5f32;
+  fn init_vertex_buffer(device: &Device, size: f32) -> Buffer {
     #[rustfmt::skip]
     let vertex_buffer_data: [f32; 6] = [
-      -1.0 * size, -2.0 * size,
+      -size, -2.0 * size,
       size, -2.0 * size,
       0.0, 2.0 * size,
     ];
@@ -113,6 +296,14 @@ impl RenderPass {
     })
   }
 
+  fn init_render_params_buffer(device: &Device, max_speed: f32) -> Buffer {
+    device.create_buffer_init(&BufferInitDescriptor {
+      label: Some("Render params buffer"),
+      contents: bytemuck::bytes_of(&RenderParams::new(ColorMode::default(), max_speed)),
+      usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+    })
+  }
+
   fn init_bind_group_layout(device: &Device) -> BindGroupLayout {
     device.create_bind_group_layout(&BindGroupLayoutDescriptor {
       label: Some("Particle bind group layout"),
@@ -141,10 +332,37 @@ impl RenderPass {
     })
   }
 
-  fn init_bind_group(device: &Device, bind_group_layout: &BindGroupLayout, particle_buffer: &Buffer, window_buffer: &Buffer) -> BindGroup {
+  fn init_obstacle_bind_group_layout(device: &Device) -> BindGroupLayout {
+    device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+      label: Some("Obstacle draw bind group layout"),
+      entries: &[BindGroupLayoutEntry {
+        binding: 0,
+        visibility: ShaderStages::VERTEX_FRAGMENT,
+        ty: BindingType::Buffer {
+          ty: BufferBindingType::Storage { read_only: true },
+          has_dynamic_offset: false,
+          min_binding_size: None,
+        },
+        count: None,
+      }],
+    })
+  }
+
+  fn init_obstacle_bind_group(device: &Device, bind_group_layout: &BindGroupLayout, obstacle_buffer: &Buffer) -> BindGroup {
+    device.create_bind_group(&BindGroupDescriptor {
+      label: Some("Obstacle draw bind group"),
+      layout: bind_group_layout,
+      entries: &[BindGroupEntry {
+        binding: 0,
+        resource: obstacle_buffer.as_entire_binding(),
+      }],
+    })
+  }
+
+  fn init_bind_group(device: &Device, bind_group_layout: &BindGroupLayout, particle_buffer: &Buffer, render_params_buffer: &Buffer) -> BindGroup {
     device.create_bind_group(&BindGroupDescriptor {
       label: Some("Particle Bind Group"),
-      layout: &bind_group_layout,
+      layout: bind_group_layout,
       entries: &[
         BindGroupEntry {
           binding: 0,
@@ -152,9 +370,19 @@ impl RenderPass {
         },
         BindGroupEntry {
           binding: 1,
-          resource: window_buffer.as_entire_binding(),
+          resource: render_params_buffer.as_entire_binding(),
         },
       ],
     })
   }
 }
+
+impl RenderParams {
+  fn new(color_mode: ColorMode, max_speed: f32) -> RenderParams {
+    RenderParams {
+      color_mode: color_mode as u32,
+      max_speed,
+      _padding: [0; 2],
+    }
+  }
+}
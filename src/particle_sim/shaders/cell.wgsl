@@ -0,0 +1,12 @@
+// cells are params.cell_size wide, which is at least the largest interaction radius,
+// so every neighbor of a particle lies in the 3x3 block of cells around it
+fn cell_coord(p: vec2<f32>) -> vec2<i32> {
+  return vec2<i32>(floor(p / params.cell_size));
+}
+
+// unbounded desktop space is folded into a fixed number of buckets;
+// colliding cells share a bucket and are told apart with cell_coord
+fn cell_hash(cell: vec2<i32>, bucket_count: u32) -> u32 {
+  let h = (bitcast<u32>(cell.x) * 73856093u) ^ (bitcast<u32>(cell.y) * 19349663u);
+  return h % bucket_count;
+}
@@ -2,6 +2,7 @@ struct Particle {
   pos: vec2<f32>,
   vel: vec2<f32>,
   color: vec4<f32>,
+  species: u32,
 };
 
 struct Window {
@@ -9,13 +10,45 @@ struct Window {
   bottom_right: vec2<f32>,
 }
 
+struct RenderParams {
+  color_mode: u32,
+  max_speed: f32,
+}
+
 struct VOut {
   @builtin(position) pos: vec4<f32>,
   @location(0) color: vec4<f32>,
 };
 
 @group(0) @binding(0) var<storage, read> particles: array<Particle>;
-@group(0) @binding(1) var<uniform> window: Window;
+@group(0) @binding(1) var<uniform> render_params: RenderParams;
+// the window being drawn, every window has its own
+@group(1) @binding(0) var<uniform> window: Window;
+
+const TAU: f32 = 6.28318530718;
+
+fn hue_to_rgb(hue: f32) -> vec3<f32> {
+  let k = abs(fract(hue + vec3<f32>(0.0, 2.0 / 3.0, 1.0 / 3.0)) * 6.0 - 3.0) - 1.0;
+  return clamp(k, vec3<f32>(0.0), vec3<f32>(1.0));
+}
+
+fn particle_color(p: Particle) -> vec4<f32> {
+  switch render_params.color_mode {
+    // heading
+    case 1u: {
+      let hue = atan2(p.vel.y, p.vel.x) / TAU + 0.5;
+      return vec4<f32>(hue_to_rgb(hue), 1.0);
+    }
+    // speed
+    case 2u: {
+      let t = clamp(length(p.vel) / render_params.max_speed, 0.0, 1.0);
+      return vec4<f32>(mix(vec3<f32>(0.1, 0.3, 1.0), vec3<f32>(1.0, 0.2, 0.1), t), 1.0);
+    }
+    default: {
+      return p.color;
+    }
+  }
+}
 
 @vertex
 fn main_vs(
@@ -37,7 +70,7 @@ fn main_vs(
   var ndc = uv * 2.0 - 1.0;
   ndc.y = -ndc.y;
 
-  return VOut(vec4<f32>(ndc, 0.0, 1.0), p.color);
+  return VOut(vec4<f32>(ndc, 0.0, 1.0), particle_color(p));
 }
 
 @fragment
@@ -0,0 +1,121 @@
+const SCAN_SIZE: u32 = 256u;
+
+@group(0) @binding(0) var<storage, read> particlesSrc: array<Particle>;
+@group(0) @binding(1) var<storage, read_write> particle_buckets: array<u32>;
+@group(0) @binding(2) var<storage, read_write> bucket_counts: array<atomic<u32>>;
+@group(0) @binding(3) var<storage, read_write> bucket_starts: array<u32>;
+@group(0) @binding(4) var<storage, read_write> bucket_offsets: array<atomic<u32>>;
+@group(0) @binding(5) var<storage, read_write> sorted_particles: array<Particle>;
+@group(0) @binding(6) var<storage, read_write> sorted_indices: array<u32>;
+@group(0) @binding(7) var<uniform> params: Params;
+
+var<workgroup> partial_sums: array<u32, SCAN_SIZE>;
+
+@compute
+@workgroup_size(64)
+fn assign_cells(@builtin(global_invocation_id) global_invocation_id: vec3<u32>) {
+  let id = global_invocation_id.x;
+  if (id >= params.particle_count) {
+    return;
+  }
+
+  let bucket = cell_hash(cell_coord(particlesSrc[id].pos), arrayLength(&bucket_counts));
+  particle_buckets[id] = bucket;
+  atomicAdd(&bucket_counts[bucket], 1u);
+}
+
+// exclusive prefix sum over bucket_counts, done by a single workgroup:
+// every invocation sums a contiguous chunk, chunk totals are scanned in workgroup memory
+@compute
+@workgroup_size(256)
+fn prefix_sum(@builtin(local_invocation_index) lid: u32) {
+  let bucket_count = arrayLength(&bucket_counts);
+  let chunk = (bucket_count + SCAN_SIZE - 1u) / SCAN_SIZE;
+  let begin = min(lid * chunk, bucket_count);
+  let end = min(begin + chunk, bucket_count);
+
+  var chunk_sum = 0u;
+  for (var i = begin; i < end; i++) {
+    chunk_sum += atomicLoad(&bucket_counts[i]);
+  }
+  partial_sums[lid] = chunk_sum;
+  workgroupBarrier();
+
+  for (var offset = 1u; offset < SCAN_SIZE; offset *= 2u) {
+    var value = partial_sums[lid];
+    if (lid >= offset) {
+      value += partial_sums[lid - offset];
+    }
+    workgroupBarrier();
+    partial_sums[lid] = value;
+    workgroupBarrier();
+  }
+
+  var running = partial_sums[lid] - chunk_sum;
+  for (var i = begin; i < end; i++) {
+    bucket_starts[i] = running;
+    atomicStore(&bucket_offsets[i], running);
+    running += atomicLoad(&bucket_counts[i]);
+  }
+
+  if (lid == SCAN_SIZE - 1u) {
+    bucket_starts[bucket_count] = running;
+  }
+}
+
+// lists the particles of every bucket in `sorted_indices`, in whatever order the atomics hand out slots
+@compute
+@workgroup_size(64)
+fn scatter(@builtin(global_invocation_id) global_invocation_id: vec3<u32>) {
+  let id = global_invocation_id.x;
+  if (id >= params.particle_count) {
+    return;
+  }
+
+  let slot = atomicAdd(&bucket_offsets[particle_buckets[id]], 1u);
+  sorted_indices[slot] = id;
+}
+
+// atomic scatter leaves each bucket in a different order every step, placing every particle at its rank by index
+// makes the force pass visit neighbors in a fixed order, so float sums and the whole run are reproducible.
+// The rank is the number of lower indices in the same bucket, the final slot is kept in `particle_buckets`
+@compute
+@workgroup_size(64)
+fn rank_particles(@builtin(global_invocation_id) global_invocation_id: vec3<u32>) {
+  let id = global_invocation_id.x;
+  if (id >= params.particle_count) {
+    return;
+  }
+
+  let bucket = particle_buckets[id];
+  let begin = bucket_starts[bucket];
+  let end = bucket_starts[bucket + 1u];
+  var slot = begin;
+  for (var i = begin; i < end; i++) {
+    if (sorted_indices[i] < id) {
+      slot++;
+    }
+  }
+
+  sorted_particles[slot] = particlesSrc[id];
+  particle_buckets[id] = slot;
+}
+
+// separate from `rank_particles`, which still reads the unordered indices
+@compute
+@workgroup_size(64)
+fn place_indices(@builtin(global_invocation_id) global_invocation_id: vec3<u32>) {
+  let id = global_invocation_id.x;
+  if (id >= params.particle_count) {
+    return;
+  }
+
+  sorted_indices[particle_buckets[id]] = id;
+}
+
+struct Particle {
+  pos: vec2<f32>,
+  vel: vec2<f32>,
+  color: vec4<f32>,
+  species: u32,
+};
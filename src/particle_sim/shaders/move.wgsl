@@ -1,51 +1,44 @@
-const MAX_SPEED = 50.0;
-
-const OUTSIDE_STRENGTH: f32 = 10.0;
-
-const ACCEL_STRENGTH: f32 = 0.1;
-
-const ALIGNMENT_STRENGTH: f32 = 10.0;
-const ALIGNMENT_RADIUS: f32 = 200.0;
-
-const COHESION_FAR_STRENGTH: f32 = 1.0;
-const COHESION_FAR_RADIUS: f32 = 150.0;
-const COHESION_CLOSE_STRENGTH: f32 = 20.0;
-const COHESION_CLOSE_RADIUS: f32 = 100.0;
-
-const SEPARATION_STRENGTH: f32 = 30.0;
-const SEPARATION_RADIUS: f32 = 15.0;
-
-const XENOPHOBIA_STRENGTH: f32 = 5.0;
-const XENOPHOBIA_START_RADIUS: f32 = 200.0;
-const XENOPHOBIA_END_RADIUS: f32 = 250.0;
-
+// mirrored by `step_cpu` in cpu.rs, keep the two in sync
 @group(0) @binding(0) var<uniform> params: Params;
 @group(0) @binding(1) var<storage, read> windows: array<Window>;
 @group(0) @binding(2) var<storage, read> particlesSrc: array<Particle>;
 @group(0) @binding(3) var<storage, read_write> particlesDst: array<Particle>;
+@group(0) @binding(4) var<storage, read> bucket_starts: array<u32>;
+@group(0) @binding(5) var<storage, read> sorted_particles: array<Particle>;
+@group(0) @binding(6) var<storage, read> sorted_indices: array<u32>;
+@group(1) @binding(0) var<storage, read> species_table: array<Species>;
+@group(1) @binding(1) var<storage, read> species_weights: array<Weights>;
+@group(2) @binding(0) var<storage, read> obstacles: array<Obstacle>;
 
 @compute
 @workgroup_size(64)
 fn main(@builtin(global_invocation_id) global_invocation_id: vec3<u32>) {
-  let total = arrayLength(&particlesSrc);
+  let total = params.particle_count;
   let id = global_invocation_id.x;
   if (id >= total) {
     return;
   }
 
+  let rules = params.rules;
   var pos: vec2<f32> = particlesSrc[id].pos;
   var vel: vec2<f32> = particlesSrc[id].vel;
   var color: vec4<f32> = particlesSrc[id].color;
+  let species_count = arrayLength(&species_table);
+  let species_id = particlesSrc[id].species;
+  let own_species = species_index(species_id, species_count);
+  let species = species_table[own_species];
   var total_force: vec2<f32> = vec2(0.0);
 
   // force that speeds up particles, so slow particles won't stay slow for long
-  let accel_force = ACCEL_STRENGTH * safe_normalize(vel);
+  let accel_force = rules.accel_strength * safe_normalize(vel);
 
-  // force that is applied to particles outside of the window, directed to the window center
+  // force that is applied to particles outside of the windows, directed to the closest boundary;
+  // wrapped and open boundaries move particles back in directly instead
   let fut_pos = pos + vel;
   let sdf = sdf(fut_pos);
-  let direction = safe_normalize(sdf.center - pos);
-  let multiplier = OUTSIDE_STRENGTH * max(0.0f, sdf.value);
+  let direction = -safe_normalize(sdf.gradient);
+  let pulls_back = params.boundary == BOUNDARY_SOFT || params.boundary == BOUNDARY_BOUNCE;
+  let multiplier = select(0.0, rules.outside_strength * max(0.0f, sdf.value), pulls_back);
   let outside_force = direction * multiplier;
 
   // force used to make out distinct groups
@@ -57,74 +50,266 @@ fn main(@builtin(global_invocation_id) global_invocation_id: vec3<u32>) {
   var cohesion_close: vec2<f32> = vec2(0.0);
   var separation: vec2<f32> = vec2(0.0);
 
-  let i_max = arrayLength(&particlesSrc);
-  for (var i = 0u; i < i_max; i += 1u) {
-    let other = particlesSrc[i];
-    let dist = length(other.pos - pos);
-
-    if dist <= ALIGNMENT_RADIUS {
-      alignment += other.vel;
+  // index of the particle the color was taken from; the highest index wins,
+  // same as the last match of a full scan in index order
+  var color_source: i32 = -1;
+
+  let bucket_count = arrayLength(&bucket_starts) - 1u;
+  let cell = cell_coord(pos);
+  for (var dy = -1; dy <= 1; dy++) {
+    for (var dx = -1; dx <= 1; dx++) {
+      let neighbor_cell = cell + vec2<i32>(dx, dy);
+      let bucket = cell_hash(neighbor_cell, bucket_count);
+
+      for (var i = bucket_starts[bucket]; i < bucket_starts[bucket + 1u]; i += 1u) {
+        let other = sorted_particles[i];
+        if any(cell_coord(other.pos) != neighbor_cell) {
+          continue;
+        }
+
+        let dist = length(other.pos - pos);
+        let other_species = species_index(other.species, species_count);
+        let weights = species_weights[own_species * species_count + other_species];
+
+        if dist <= species.alignment_radius {
+          alignment += weights.alignment * other.vel;
+        }
+
+        if dist <= species.cohesion_radius {
+          cohesion_far += weights.cohesion * (other.pos - pos);
+        }
+
+        if dist <= rules.cohesion_close_radius {
+          cohesion_close += weights.cohesion * (other.pos - pos);
+
+          // color only spreads within a species
+          let angle_dif = dot(safe_normalize(vel), safe_normalize(other.vel));
+          let other_index = i32(sorted_indices[i]);
+          if other_species == own_species && length(vel) <= length(other.vel) && angle_dif > rules.contagion_min_cos && other_index > color_source {
+            color = other.color;
+            color_source = other_index;
+          }
+        }
+
+        if dist <= species.separation_radius {
+          separation += weights.separation * (pos - other.pos);
+        }
+
+        if dist >= rules.xenophobia_start_radius && dist <= rules.xenophobia_end_radius {
+          xenophobia += pos - other.pos;
+        }
+      }
     }
+  }
 
-    if dist <= COHESION_FAR_RADIUS {
-      cohesion_far += other.pos - pos;
-    }
+  let alignment_force = safe_normalize(alignment) * rules.alignment_strength;
+  let cohesion_far_force = safe_normalize(cohesion_far) * rules.cohesion_far_strength;
+  let cohesion_close_force = safe_normalize(cohesion_close) * rules.cohesion_close_strength;
+  let separation_force = safe_normalize(separation) * rules.separation_strength;
+  let xenophobia_force = safe_normalize(xenophobia) * rules.xenophobia_strength;
+
+  total_force = outside_force + accel_force + alignment_force + cohesion_far_force + cohesion_close_force + separation_force + xenophobia_force;
+  total_force += interaction_force(pos) + obstacle_force(pos);
 
-    if dist <= COHESION_CLOSE_RADIUS {
-      cohesion_close += other.pos - pos;
+  vel += total_force * params.dt;
+  let speed = length(vel);
+  vel = safe_normalize(vel) * clamp(speed, 0.0, species.max_speed);
 
-      const cos_30: f32 = 0.866;
-      let angle_dif = dot(safe_normalize(vel), safe_normalize(other.vel));
-      if length(vel) <= length(other.vel) && angle_dif > cos_30 {
-        color = other.color;
+  let old_pos = pos;
+  pos += vel * params.dt;
+
+  switch params.boundary {
+    case BOUNDARY_WRAP: {
+      pos = wrap(pos);
+    }
+    case BOUNDARY_BOUNCE: {
+      bounce(old_pos, &pos, &vel);
+    }
+    case BOUNDARY_OPEN: {
+      if sdf(pos).value > 0.0 {
+        respawn(id, &pos, &vel);
       }
     }
+    default: {}
+  }
 
-    if dist <= SEPARATION_RADIUS {
-      separation += pos - other.pos;
-    }
+  particlesDst[id] = Particle(pos, vel, color, species_id);
+}
+
+const BOUNDARY_SOFT: u32 = 0u;
+const BOUNDARY_WRAP: u32 = 1u;
+const BOUNDARY_BOUNCE: u32 = 2u;
+const BOUNDARY_OPEN: u32 = 3u;
+
+// distance to the simulated area and its gradient: the world rectangle when wrapping,
+// the union of all windows otherwise, blended with a smooth minimum of radius params.smoothing
+fn sdf(p: vec2<f32>) -> SdfResult {
+  if params.boundary == BOUNDARY_WRAP {
+    return rect_sdf(p, params.world_min, params.world_max);
+  }
 
-    if dist >= XENOPHOBIA_START_RADIUS && dist <= XENOPHOBIA_END_RADIUS {
-      xenophobia += pos - other.pos;
+  var result = SdfResult(vec2<f32>(0.0), 1000.0);
+  for (var i = 0u; i < params.window_count; i++) {
+    let w = windows[i];
+    let window_sdf = rect_sdf(p, w.top_left, w.bottom_right);
+    if i == 0u {
+      result = window_sdf;
+    } else {
+      result = smooth_min(result, window_sdf, params.smoothing);
     }
   }
 
-  let alignment_force = safe_normalize(alignment) * ALIGNMENT_STRENGTH;
-  let cohesion_far_force = safe_normalize(cohesion_far) * COHESION_FAR_STRENGTH;
-  let cohesion_close_force = safe_normalize(cohesion_close) * COHESION_CLOSE_STRENGTH;
-  let separation_force = safe_normalize(separation) * SEPARATION_STRENGTH;
-  let xenophobia_force = safe_normalize(xenophobia) * XENOPHOBIA_STRENGTH;
+  return result;
+}
 
-  total_force = outside_force + accel_force + alignment_force + cohesion_far_force + cohesion_close_force + separation_force + xenophobia_force;
+fn rect_sdf(p: vec2<f32>, top_left: vec2<f32>, bottom_right: vec2<f32>) -> SdfResult {
+  let center = (top_left + bottom_right) * 0.5;
+  let half_size = (bottom_right - top_left) * 0.5;
+  let d = abs(p - center) - half_size;
+  let outside = max(d, vec2<f32>(0.0));
+  let value = length(outside) + min(max(d.x, d.y), 0.0);
+
+  // outside the gradient points away from the closest point on the edge, inside away from the closest edge
+  let axis = select(vec2<f32>(0.0, 1.0), vec2<f32>(1.0, 0.0), d.x > d.y);
+  let direction = select(axis, outside / length(outside), value > 0.0);
+  return SdfResult(sign(p - center) * direction, value);
+}
 
-  vel += total_force * params.dt;
-  let speed = length(vel);
-  vel = safe_normalize(vel) * clamp(speed, 0.0, MAX_SPEED);
+// polynomial smooth minimum, the gradients are blended with the same weight as the distances
+fn smooth_min(a: SdfResult, b: SdfResult, k: f32) -> SdfResult {
+  if k <= 0.0 {
+    if a.value < b.value {
+      return a;
+    }
+    return b;
+  }
 
-  pos += vel * params.dt;
+  let t = clamp(0.5 + 0.5 * (b.value - a.value) / k, 0.0, 1.0);
+  let value = mix(b.value, a.value, t) - k * t * (1.0 - t);
+  return SdfResult(mix(b.gradient, a.gradient, t), value);
+}
 
-  particlesDst[id] = Particle(pos, vel, color);
+// moves p back into the world rectangle from the opposite side
+fn wrap(p: vec2<f32>) -> vec2<f32> {
+  let size = params.world_max - params.world_min;
+  if any(size <= vec2<f32>(0.0)) {
+    return p;
+  }
+  return p - size * floor((p - params.world_min) / size);
 }
 
-fn sdf(p: vec2<f32>) -> SdfResult {
-  var value = 1000.0;
-  var center = vec2<f32>(0.0, 0.0);
+// reflects particles that left every window off the edges of the window they were in
+fn bounce(old_pos: vec2<f32>, pos: ptr<function, vec2<f32>>, vel: ptr<function, vec2<f32>>) {
+  if sdf(*pos).value <= 0.0 {
+    return;
+  }
 
   for (var i = 0u; i < params.window_count; i++) {
     let w = windows[i];
-    let this_center = (w.top_left + w.bottom_right) * 0.5;
-    let half_size = (w.bottom_right - w.top_left) * 0.5;
-    let d = abs(p - this_center) - half_size;
+    if rect_sdf(old_pos, w.top_left, w.bottom_right).value > 0.0 {
+      continue;
+    }
+
+    if (*pos).x < w.top_left.x {
+      (*pos).x = 2.0 * w.top_left.x - (*pos).x;
+      (*vel).x = abs((*vel).x);
+    } else if (*pos).x > w.bottom_right.x {
+      (*pos).x = 2.0 * w.bottom_right.x - (*pos).x;
+      (*vel).x = -abs((*vel).x);
+    }
+
+    if (*pos).y < w.top_left.y {
+      (*pos).y = 2.0 * w.top_left.y - (*pos).y;
+      (*vel).y = abs((*vel).y);
+    } else if (*pos).y > w.bottom_right.y {
+      (*pos).y = 2.0 * w.bottom_right.y - (*pos).y;
+      (*vel).y = -abs((*vel).y);
+    }
+    return;
+  }
+}
+
+// places the particle at a random spot of a random window, keeping its speed;
+// seeded by particle id and step so runs stay reproducible
+fn respawn(id: u32, pos: ptr<function, vec2<f32>>, vel: ptr<function, vec2<f32>>) {
+  if params.window_count == 0u {
+    return;
+  }
+
+  var seed = pcg_hash(id ^ pcg_hash(params.step_index));
+  let w = windows[seed % params.window_count];
+
+  let offset = vec2<f32>(random_float(&seed), random_float(&seed));
+  let angle = random_float(&seed) * TAU;
+
+  *pos = w.top_left + (w.bottom_right - w.top_left) * offset;
+  *vel = vec2<f32>(cos(angle), sin(angle)) * length(*vel);
+}
+
+const TAU: f32 = 6.28318530718;
 
-    let this_value = length(max(d, vec2<f32>(0.0))) + min(max(d.x, d.y), 0.0);
+fn pcg_hash(v: u32) -> u32 {
+  let state = v * 747796405u + 2891336453u;
+  let word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+  return (word >> 22u) ^ word;
+}
+
+// uniform in 0..1, only 24 bits so the conversion to f32 is exact
+fn random_float(seed: ptr<function, u32>) -> f32 {
+  *seed = pcg_hash(*seed);
+  return f32(*seed >> 8u) / 16777216.0;
+}
+
+// force from the mouse interaction, fading out linearly towards the edge of its radius
+fn interaction_force(pos: vec2<f32>) -> vec2<f32> {
+  let interaction = params.interaction;
+  let offset = interaction.pos - pos;
+  let dist = length(offset);
+  if interaction.mode == 0u || dist > interaction.radius {
+    return vec2(0.0);
+  }
 
-    if value > this_value {
-      center = this_center;
-      value = this_value;
+  let direction = safe_normalize(offset);
+  var force = vec2(0.0);
+  switch interaction.mode {
+    case 1u: { force = direction; }
+    case 2u: { force = -direction; }
+    case 3u: { force = vec2(-direction.y, direction.x); }
+    default: {}
+  }
+
+  return force * interaction.strength * (1.0 - dist / interaction.radius);
+}
+
+// pushes particles out along the SDF gradient of every obstacle closer than obstacle_distance,
+// at full strength on the surface and inside
+fn obstacle_force(pos: vec2<f32>) -> vec2<f32> {
+  let rules = params.rules;
+  var force = vec2(0.0);
+  for (var i = 0u; i < params.obstacle_count; i++) {
+    let obstacle = obstacles[i];
+    let d = obstacle_sdf(obstacle, pos);
+    if d < rules.obstacle_distance {
+      force += obstacle_gradient(obstacle, pos) * (1.0 - max(d, 0.0) / rules.obstacle_distance);
     }
   }
 
-  return SdfResult(center, value);
+  return force * rules.obstacle_strength;
+}
+
+// central differences, the SDF is only piecewise smooth
+fn obstacle_gradient(obstacle: Obstacle, pos: vec2<f32>) -> vec2<f32> {
+  let e = vec2<f32>(1.0, 0.0);
+  let gradient = vec2<f32>(
+    obstacle_sdf(obstacle, pos + e.xy) - obstacle_sdf(obstacle, pos - e.xy),
+    obstacle_sdf(obstacle, pos + e.yx) - obstacle_sdf(obstacle, pos - e.yx),
+  );
+  return safe_normalize(gradient);
+}
+
+// ids past the end of the table, e.g. from an older snapshot, use the last species
+fn species_index(species: u32, species_count: u32) -> u32 {
+  return min(species, species_count - 1u);
 }
 
 fn safe_normalize(v: vec2<f32>) -> vec2<f32> {
@@ -133,19 +318,29 @@ fn safe_normalize(v: vec2<f32>) -> vec2<f32> {
 }
 
 struct SdfResult {
-  center: vec2<f32>,
+  gradient: vec2<f32>,
   value: f32,
 }
 
-struct Params {
-  dt: f32,
-  window_count: u32
-};
-
 struct Particle {
   pos: vec2<f32>,
   vel: vec2<f32>,
   color: vec4<f32>,
+  species: u32,
+};
+
+struct Species {
+  max_speed: f32,
+  alignment_radius: f32,
+  cohesion_radius: f32,
+  separation_radius: f32,
+};
+
+struct Weights {
+  alignment: f32,
+  cohesion: f32,
+  separation: f32,
+  _padding: f32,
 };
 
 struct Window {
@@ -0,0 +1,51 @@
+// shared by move.wgsl and obstacles.wgsl, mirrored by `Obstacle::sdf` in obstacle.rs
+struct Obstacle {
+  // 0 circle, 1 box, 2 capsule, 3 polygon
+  kind: u32,
+  point_count: u32,
+  radius: f32,
+  _padding: f32,
+  // circle: center; box: min and max; capsule: both ends; polygon: corners
+  points: array<vec2<f32>, 16>,
+};
+
+// signed distance from p to the obstacle, negative inside
+fn obstacle_sdf(obstacle: Obstacle, p: vec2<f32>) -> f32 {
+  var points = obstacle.points;
+  switch obstacle.kind {
+    case 0u: {
+      return length(p - points[0]) - obstacle.radius;
+    }
+    case 1u: {
+      let center = (points[0] + points[1]) * 0.5;
+      let half_size = (points[1] - points[0]) * 0.5;
+      let d = abs(p - center) - half_size;
+      return length(max(d, vec2<f32>(0.0))) + min(max(d.x, d.y), 0.0);
+    }
+    case 2u: {
+      let pa = p - points[0];
+      let ba = points[1] - points[0];
+      let h = clamp(dot(pa, ba) / max(dot(ba, ba), 1e-6), 0.0, 1.0);
+      return length(pa - ba * h) - obstacle.radius;
+    }
+    default: {
+      // distance to the closest edge, negated when an even-odd crossing test puts p inside
+      var d = dot(p - points[0], p - points[0]);
+      var inside_sign = 1.0;
+      var j = obstacle.point_count - 1u;
+      for (var i = 0u; i < obstacle.point_count; i++) {
+        let e = points[j] - points[i];
+        let w = p - points[i];
+        let b = w - e * clamp(dot(w, e) / max(dot(e, e), 1e-6), 0.0, 1.0);
+        d = min(d, dot(b, b));
+
+        let crossing = vec3<bool>(p.y >= points[i].y, p.y < points[j].y, e.x * w.y > e.y * w.x);
+        if all(crossing) || all(!crossing) {
+          inside_sign = -inside_sign;
+        }
+        j = i;
+      }
+      return inside_sign * sqrt(d);
+    }
+  }
+}
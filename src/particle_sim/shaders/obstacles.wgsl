@@ -0,0 +1,61 @@
+struct Window {
+  top_left: vec2<f32>,
+  bottom_right: vec2<f32>,
+}
+
+struct VOut {
+  @builtin(position) pos: vec4<f32>,
+  @location(0) world_pos: vec2<f32>,
+  @location(1) @interpolate(flat) index: u32,
+};
+
+@group(0) @binding(0) var<storage, read> obstacles: array<Obstacle>;
+@group(1) @binding(0) var<uniform> window: Window;
+
+const FILL_COLOR: vec3<f32> = vec3<f32>(0.22, 0.22, 0.27);
+const RIM_COLOR: vec3<f32> = vec3<f32>(0.55, 0.55, 0.65);
+
+// desktop-space rectangle around the obstacle as min xy, max xy
+fn obstacle_bounds(obstacle: Obstacle) -> vec4<f32> {
+  var points = obstacle.points;
+  var low = points[0];
+  var high = points[0];
+  for (var i = 1u; i < obstacle.point_count; i++) {
+    low = min(low, points[i]);
+    high = max(high, points[i]);
+  }
+
+  // one extra pixel for the antialiased edge
+  let margin = obstacle.radius + 1.0;
+  return vec4<f32>(low - margin, high + margin);
+}
+
+// one quad per instance, drawn as a 4 vertex triangle strip covering the obstacle bounds
+@vertex
+fn main_vs(
+  @builtin(vertex_index) vertex: u32,
+  @builtin(instance_index) index: u32,
+) -> VOut {
+  let bounds = obstacle_bounds(obstacles[index]);
+  let corner = vec2<f32>(f32(vertex & 1u), f32(vertex >> 1u));
+  let world_pos = mix(bounds.xy, bounds.zw, corner);
+
+  let uv = (world_pos - window.top_left) / (window.bottom_right - window.top_left);
+
+  var ndc = uv * 2.0 - 1.0;
+  ndc.y = -ndc.y;
+
+  return VOut(vec4<f32>(ndc, 0.0, 1.0), world_pos, index);
+}
+
+@fragment
+fn main_fs(in: VOut) -> @location(0) vec4<f32> {
+  let d = obstacle_sdf(obstacles[in.index], in.world_pos);
+  let coverage = clamp(0.5 - d, 0.0, 1.0);
+  if coverage <= 0.0 {
+    discard;
+  }
+
+  let rim = smoothstep(-3.0, -1.5, d);
+  return vec4<f32>(mix(FILL_COLOR, RIM_COLOR, rim), coverage);
+}
@@ -0,0 +1,55 @@
+struct Params {
+  dt: f32,
+  window_count: u32,
+  cell_size: f32,
+  particle_count: u32,
+  rules: BoidRules,
+  interaction: Interaction,
+  obstacle_count: u32,
+  // 0 soft, 1 wrap, 2 bounce, 3 open
+  boundary: u32,
+  step_index: u32,
+  smoothing: f32,
+  world_min: vec2<f32>,
+  world_max: vec2<f32>,
+};
+
+struct BoidRules {
+  max_speed: f32,
+  outside_strength: f32,
+  accel_strength: f32,
+
+  alignment_strength: f32,
+  alignment_radius: f32,
+
+  cohesion_far_strength: f32,
+  cohesion_far_radius: f32,
+  cohesion_close_strength: f32,
+  cohesion_close_radius: f32,
+
+  separation_strength: f32,
+  separation_radius: f32,
+
+  xenophobia_strength: f32,
+  xenophobia_start_radius: f32,
+  xenophobia_end_radius: f32,
+
+  contagion_min_cos: f32,
+
+  obstacle_strength: f32,
+  obstacle_distance: f32,
+  _padding_0: f32,
+  _padding_1: f32,
+  _padding_2: f32,
+};
+
+struct Interaction {
+  pos: vec2<f32>,
+  // 0 none, 1 attract, 2 repel, 3 vortex
+  mode: u32,
+  radius: f32,
+  strength: f32,
+  _padding_0: f32,
+  _padding_1: f32,
+  _padding_2: f32,
+};
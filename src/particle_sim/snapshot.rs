@@ -0,0 +1,231 @@
+use crate::particle_sim::{obstacle::Obstacle, params::Params, particle::Particle, window::Window};
+use std::{fs, path::Path};
+
+const MAGIC: &[u8; 8] = b"BOIDSNAP";
+const VERSION: u32 = 6;
+
+/// Complete simulation state, enough to continue a session exactly where it was saved.
+///
+/// Binary layout (version 6), integers little endian, structs in their GPU layout:
+/// magic `BOIDSNAP`, version `u32`, seed `u64`, spawned `u64`, `Params`, window count `u32` + `Window`s,
+/// obstacle count `u32` + `Obstacle`s, particle count `u32` + `Particle`s
+#[derive(Clone)]
+pub struct Snapshot {
+  pub seed: u64,
+  /// particles spawned from `seed` so far, particles added after loading continue from there
+  pub spawned: u64,
+  pub params: Params,
+  pub windows: Vec<Window>,
+  /// including the ones placed at runtime
+  pub obstacles: Vec<Obstacle>,
+  pub particles: Vec<Particle>,
+}
+
+impl Snapshot {
+  pub fn save(&self, path: &Path) -> Result<(), SnapshotError> {
+    Ok(fs::write(path, self.to_bytes())?)
+  }
+
+  pub fn load(path: &Path) -> Result<Snapshot, SnapshotError> {
+    Snapshot::from_bytes(&fs::read(path)?)
+  }
+
+  /// Errors unless the particles fit into particle buffers of at most `max_particles`, which can't be empty either
+  pub fn check_particle_count(&self, max_particles: u32) -> Result<(), SnapshotError> {
+    if self.particles.is_empty() || self.particles.len() > max_particles as usize {
+      return Err(SnapshotError::ParticleCount(self.particles.len(), max_particles));
+    }
+
+    Ok(())
+  }
+
+  pub fn to_bytes(&self) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(MAGIC);
+    bytes.extend_from_slice(&VERSION.to_le_bytes());
+    bytes.extend_from_slice(&self.seed.to_le_bytes());
+    bytes.extend_from_slice(&self.spawned.to_le_bytes());
+    bytes.extend_from_slice(bytemuck::bytes_of(&self.params));
+    bytes.extend_from_slice(&(self.windows.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(bytemuck::cast_slice(&self.windows));
+    bytes.extend_from_slice(&(self.obstacles.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(bytemuck::cast_slice(&self.obstacles));
+    bytes.extend_from_slice(&(self.particles.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(bytemuck::cast_slice(&self.particles));
+    bytes
+  }
+
+  pub fn from_bytes(bytes: &[u8]) -> Result<Snapshot, SnapshotError> {
+    let mut reader = Reader { bytes };
+    if reader.take(MAGIC.len())? != MAGIC {
+      return Err(SnapshotError::InvalidMagic);
+    }
+
+    let version = u32::from_le_bytes(reader.array()?);
+    if version != VERSION {
+      return Err(SnapshotError::UnsupportedVersion(version));
+    }
+
+    let seed = u64::from_le_bytes(reader.array()?);
+    let spawned = u64::from_le_bytes(reader.array()?);
+    let params = bytemuck::pod_read_unaligned(reader.take(size_of::<Params>())?);
+    let windows = reader.pod_vec()?;
+    let obstacles = reader.pod_vec()?;
+    let particles = reader.pod_vec()?;
+
+    if !reader.bytes.is_empty() {
+      return Err(SnapshotError::TrailingBytes(reader.bytes.len()));
+    }
+
+    Ok(Snapshot {
+      seed,
+      spawned,
+      params,
+      windows,
+      obstacles,
+      particles,
+    })
+  }
+}
+
+struct Reader<'a> {
+  bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+  fn take(&mut self, len: usize) -> Result<&'a [u8], SnapshotError> {
+    if self.bytes.len() < len {
+      return Err(SnapshotError::Truncated);
+    }
+
+    let (taken, rest) = self.bytes.split_at(len);
+    self.bytes = rest;
+    Ok(taken)
+  }
+
+  fn array<const N: usize>(&mut self) -> Result<[u8; N], SnapshotError> {
+    Ok(self.take(N)?.try_into().unwrap())
+  }
+
+  /// Count prefixed array of `T`
+  fn pod_vec<T: bytemuck::Pod>(&mut self) -> Result<Vec<T>, SnapshotError> {
+    let count = u32::from_le_bytes(self.array()?) as usize;
+    let bytes = self.take(count * size_of::<T>())?;
+    Ok(bytes.chunks_exact(size_of::<T>()).map(bytemuck::pod_read_unaligned).collect())
+  }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum SnapshotError {
+  #[error("IO error: {0}")]
+  IoError(#[from] std::io::Error),
+
+  #[error("Not a snapshot file")]
+  InvalidMagic,
+
+  #[error("Unsupported snapshot version {0}, expected {VERSION}")]
+  UnsupportedVersion(u32),
+
+  #[error("Snapshot file is truncated")]
+  Truncated,
+
+  #[error("Snapshot file has {0} unexpected trailing bytes")]
+  TrailingBytes(usize),
+
+  #[error("Snapshot holds {0} particles, the device fits between 1 and {1}")]
+  ParticleCount(usize, u32),
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::particle_sim::{obstacle::ObstacleShape, params::BoidRules};
+
+  fn snapshot() -> Snapshot {
+    let mut params = Params::with_rules(1.0 / 60.0, 2, 3, BoidRules::default());
+    params.step_index = 42;
+    params.smoothing = 8.0;
+
+    Snapshot {
+      seed: 0x1234_5678_9abc_def0,
+      spawned: 4099,
+      params,
+      windows: vec![Window::new([0.0, 0.0], [640, 480]), Window::new([700.0, 20.0], [200, 100])],
+      obstacles: vec![
+        Obstacle::from(&ObstacleShape::Circle {
+          center: [10.0, 20.0],
+          radius: 5.0,
+        }),
+        Obstacle::from(&ObstacleShape::Polygon {
+          points: vec![[0.0, 0.0], [4.0, 0.0], [0.0, 4.0]],
+        }),
+      ],
+      particles: (0..3)
+        .map(|i| Particle::new([i as f32, 2.0 * i as f32], [1.0, -1.0], [0.5; 4], i))
+        .collect(),
+    }
+  }
+
+  #[test]
+  fn round_trip_keeps_everything() {
+    let saved = snapshot();
+    let loaded = Snapshot::from_bytes(&saved.to_bytes()).unwrap();
+
+    assert_eq!(loaded.seed, saved.seed);
+    assert_eq!(loaded.spawned, saved.spawned);
+    assert_eq!(bytemuck::bytes_of(&loaded.params), bytemuck::bytes_of(&saved.params));
+    assert_eq!(
+      bytemuck::cast_slice::<Window, u8>(&loaded.windows),
+      bytemuck::cast_slice::<Window, u8>(&saved.windows)
+    );
+    assert_eq!(loaded.obstacles, saved.obstacles);
+    assert_eq!(loaded.particles, saved.particles);
+  }
+
+  #[test]
+  fn rejects_bad_magic() {
+    let mut bytes = snapshot().to_bytes();
+    bytes[0] = b'X';
+
+    assert!(matches!(Snapshot::from_bytes(&bytes), Err(SnapshotError::InvalidMagic)));
+  }
+
+  #[test]
+  fn rejects_other_versions() {
+    let mut bytes = snapshot().to_bytes();
+    bytes[MAGIC.len()..MAGIC.len() + 4].copy_from_slice(&(VERSION - 1).to_le_bytes());
+
+    assert!(matches!(Snapshot::from_bytes(&bytes), Err(SnapshotError::UnsupportedVersion(v)) if v == VERSION - 1));
+  }
+
+  #[test]
+  fn rejects_truncated_input() {
+    let bytes = snapshot().to_bytes();
+
+    // cut inside the header, the params and the particles
+    for len in [4, MAGIC.len() + 10, 40, bytes.len() - 1] {
+      assert!(
+        matches!(Snapshot::from_bytes(&bytes[..len]), Err(SnapshotError::Truncated)),
+        "{len} bytes"
+      );
+    }
+  }
+
+  #[test]
+  fn rejects_trailing_bytes() {
+    let mut bytes = snapshot().to_bytes();
+    bytes.extend_from_slice(&[0; 3]);
+
+    assert!(matches!(Snapshot::from_bytes(&bytes), Err(SnapshotError::TrailingBytes(3))));
+  }
+
+  #[test]
+  fn particle_count_has_to_fit_the_device() {
+    let mut snapshot = snapshot();
+    assert!(snapshot.check_particle_count(3).is_ok());
+    assert!(matches!(snapshot.check_particle_count(2), Err(SnapshotError::ParticleCount(3, 2))));
+
+    snapshot.particles.clear();
+    assert!(matches!(snapshot.check_particle_count(2), Err(SnapshotError::ParticleCount(0, 2))));
+  }
+}
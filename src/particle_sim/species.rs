@@ -0,0 +1,86 @@
+use crate::{config::SpeciesConfig, particle_sim::params::BoidRules};
+use bytemuck::{Pod, Zeroable};
+
+/// Per-species limits read by move.wgsl, mirrored by `Species` there
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Pod, Zeroable)]
+pub struct Species {
+  pub max_speed: f32,
+  pub alignment_radius: f32,
+  pub cohesion_radius: f32,
+  pub separation_radius: f32,
+}
+
+/// How strongly a particle reacts to neighbors of one species, mirrored by `Weights` in move.wgsl
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Pod, Zeroable)]
+pub struct Weights {
+  pub alignment: f32,
+  pub cohesion: f32,
+  pub separation: f32,
+  _padding: f32,
+}
+
+/// Species resolved against the boid rules, plus the N×N weight matrix in row-major order:
+/// `weights[a * N + b]` is how species `a` reacts to species `b`
+#[derive(Clone, Debug)]
+pub struct SpeciesTable {
+  pub species: Vec<Species>,
+  pub weights: Vec<Weights>,
+}
+
+impl SpeciesTable {
+  /// Unset values fall back to `rules`, missing weight rows to 1
+  pub fn new(configs: &[SpeciesConfig], rules: &BoidRules) -> SpeciesTable {
+    let species = configs
+      .iter()
+      .map(|config| Species {
+        max_speed: config.max_speed.unwrap_or(rules.max_speed),
+        alignment_radius: config.alignment_radius.unwrap_or(rules.alignment_radius),
+        cohesion_radius: config.cohesion_radius.unwrap_or(rules.cohesion_far_radius),
+        separation_radius: config.separation_radius.unwrap_or(rules.separation_radius),
+      })
+      .collect();
+
+    let weight = |row: &[f32], other: usize| row.get(other).copied().unwrap_or(1.0);
+    let weights = configs
+      .iter()
+      .flat_map(|config| {
+        (0..configs.len()).map(move |other| Weights {
+          alignment: weight(&config.alignment, other),
+          cohesion: weight(&config.cohesion, other),
+          separation: weight(&config.separation, other),
+          _padding: 0.0,
+        })
+      })
+      .collect();
+
+    SpeciesTable { species, weights }
+  }
+
+  // lookups for the CPU reference step, the shader indexes the buffers itself
+  #[cfg(test)]
+  pub fn count(&self) -> usize {
+    self.species.len()
+  }
+
+  /// Index into the table, ids past the end (e.g. from an older snapshot) use the last species like move.wgsl does
+  #[cfg(test)]
+  pub fn index(&self, species: u32) -> usize {
+    (species as usize).min(self.count() - 1)
+  }
+
+  #[cfg(test)]
+  pub fn weights(&self, species: usize, other: usize) -> &Weights {
+    &self.weights[species * self.count() + other]
+  }
+
+  /// Largest radius any species reacts at, the grid cells have to be at least this large
+  pub fn max_radius(&self) -> f32 {
+    self
+      .species
+      .iter()
+      .flat_map(|s| [s.alignment_radius, s.cohesion_radius, s.separation_radius])
+      .fold(1.0, f32::max)
+  }
+}
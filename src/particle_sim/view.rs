@@ -0,0 +1,61 @@
+use crate::particle_sim::window::Window;
+use wgpu::{
+  BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingType, Buffer,
+  BufferBindingType, BufferSize, BufferUsages, Device, Queue, ShaderStages,
+  util::{BufferInitDescriptor, DeviceExt},
+};
+
+/// Desktop-space rectangle a single window shows, bound as group 1 of every draw into that window.
+///
+/// Every window owns one, so several windows can be recorded into the same command buffer
+pub struct WindowView {
+  buffer: Buffer,
+  bind_group: BindGroup,
+}
+
+impl WindowView {
+  pub fn new(device: &Device) -> WindowView {
+    let buffer = device.create_buffer_init(&BufferInitDescriptor {
+      label: Some("Window view buffer"),
+      contents: bytemuck::bytes_of(&Window::empty()),
+      usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+    });
+
+    // layouts with the same entries are interchangeable, so views don't depend on the render pass that draws them
+    let bind_group = device.create_bind_group(&BindGroupDescriptor {
+      label: Some("Window view bind group"),
+      layout: &WindowView::init_layout(device),
+      entries: &[BindGroupEntry {
+        binding: 0,
+        resource: buffer.as_entire_binding(),
+      }],
+    });
+
+    WindowView { buffer, bind_group }
+  }
+
+  /// Shows `viewport` in every draw with this view of the next submit
+  pub fn update(&self, queue: &Queue, viewport: &Window) {
+    queue.write_buffer(&self.buffer, 0, bytemuck::bytes_of(viewport));
+  }
+
+  pub fn bind_group(&self) -> &BindGroup {
+    &self.bind_group
+  }
+
+  pub fn init_layout(device: &Device) -> BindGroupLayout {
+    device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+      label: Some("Window view bind group layout"),
+      entries: &[BindGroupLayoutEntry {
+        binding: 0,
+        visibility: ShaderStages::VERTEX,
+        ty: BindingType::Buffer {
+          ty: BufferBindingType::Uniform,
+          has_dynamic_offset: false,
+          min_binding_size: BufferSize::new(size_of::<Window>() as u64),
+        },
+        count: None,
+      }],
+    })
+  }
+}
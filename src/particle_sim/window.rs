@@ -16,16 +16,22 @@ impl Window {
       bottom_right: [0.0, 0.0],
     }
   }
+
+  pub fn new(top_left: [f32; 2], size: [u32; 2]) -> Window {
+    let bottom_right = [top_left[0] + size[0] as f32, top_left[1] + size[1] as f32];
+    Window { top_left, bottom_right }
+  }
+
+  pub fn size(&self) -> [u32; 2] {
+    [
+      (self.bottom_right[0] - self.top_left[0]) as u32,
+      (self.bottom_right[1] - self.top_left[1]) as u32,
+    ]
+  }
 }
 
 impl From<&WindowWrapper> for Window {
   fn from(wrapper: &WindowWrapper) -> Self {
-    let window = &wrapper.window;
-    let pos = window.inner_position().expect("Coundn't get inner position");
-    let size = window.inner_size();
-
-    let top_left = [pos.x as f32, pos.y as f32];
-    let bottom_right = [top_left[0] + size.width as f32, top_left[1] + size.height as f32];
-    Window { top_left, bottom_right }
+    wrapper.viewport().expect("Coundn't get inner position")
   }
 }